@@ -0,0 +1,63 @@
+use crate::math::Vec3;
+use crate::raytracer::{trace_ray, Camera, RenderOptions, Scene, SAMPLES_PER_PIXEL};
+use rayon::prelude::*;
+
+// Past this many samples per pixel the image has converged and rendering idles
+pub const MAX_ACCUMULATED_SAMPLES: u32 = 512;
+
+// Running sum of jittered HDR samples while the view stays the same
+pub struct Accumulator {
+    width: usize,
+    height: usize,
+    sum: Vec<Vec3>,
+    samples: u32,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            sum: vec![Vec3::zero(); width * height],
+            samples: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        if self.samples > 0 {
+            self.sum.iter_mut().for_each(|c| *c = Vec3::zero());
+            self.samples = 0;
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.samples >= MAX_ACCUMULATED_SAMPLES
+    }
+
+    // Adds SAMPLES_PER_PIXEL jittered samples to every pixel; depth of field comes
+    // from the camera's aperture
+    pub fn add_samples(&mut self, scene: &Scene, camera: &Camera, time: f32, rotation_y: f32, opts: &RenderOptions) {
+        let frame = camera.build_frame(self.width, self.height);
+        self.sum.par_chunks_mut(self.width).enumerate().for_each(|(y, row)| {
+            for (x, sum) in row.iter_mut().enumerate() {
+                for _ in 0..SAMPLES_PER_PIXEL {
+                    let ray = frame.get_ray_jittered(x as f32, y as f32);
+                    *sum = *sum + trace_ray(&ray, scene, 0, time, rotation_y, opts);
+                }
+            }
+        });
+        self.samples += SAMPLES_PER_PIXEL;
+    }
+
+    // Averages the samples into a displayable buffer
+    pub fn resolve(&self, buffer: &mut [u32]) {
+        let inv = 1.0 / self.samples.max(1) as f32;
+        buffer.par_iter_mut().zip(self.sum.par_iter()).for_each(|(pixel, sum)| {
+            *pixel = crate::color_to_u32(*sum * inv);
+        });
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use crate::math::Vec3;
+use crate::materials::{Material, MaterialId, MaterialTable};
+use crate::texture::Texture;
+
+// Numeric block IDs; 0 is always air
+pub type BlockId = u16;
+pub const AIR: BlockId = 0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockFace { Top, Bottom, Side }
+
+impl BlockFace {
+    // Face of an axis-aligned block whose outward normal is `normal`
+    pub fn from_normal(normal: Vec3) -> Self {
+        if normal.y > 0.5 {
+            BlockFace::Top
+        } else if normal.y < -0.5 {
+            BlockFace::Bottom
+        } else {
+            BlockFace::Side
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BlockFaces {
+    pub top: Material,
+    pub bottom: Material,
+    pub side: Material,
+}
+
+impl BlockFaces {
+    pub fn all(material: Material) -> Self {
+        Self {
+            top: material.clone(),
+            bottom: material.clone(),
+            side: material,
+        }
+    }
+    
+    pub fn get(&self, face: BlockFace) -> &Material {
+        match face {
+            BlockFace::Top => &self.top,
+            BlockFace::Bottom => &self.bottom,
+            BlockFace::Side => &self.side,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BlockDef {
+    pub name: String,
+    pub faces: BlockFaces,
+    pub light_level: u8, // Emitted light, 0..=15 like Minecraft; scales the emissive face's light
+}
+
+impl BlockDef {
+    pub fn new(name: &str, material: Material) -> Self {
+        Self {
+            name: name.to_string(),
+            faces: BlockFaces::all(material),
+            light_level: 0,
+        }
+    }
+
+    // Builder pattern methods
+    pub fn with_top(mut self, material: Material) -> Self {
+        self.faces.top = material;
+        self
+    }
+
+    pub fn with_bottom(mut self, material: Material) -> Self {
+        self.faces.bottom = material;
+        self
+    }
+
+    pub fn with_light_level(mut self, light_level: u8) -> Self {
+        self.light_level = light_level.min(15);
+        self
+    }
+
+    pub fn material(&self, face: BlockFace) -> &Material {
+        self.faces.get(face)
+    }
+}
+
+// Maps numeric IDs and namespaced names ("minecraft:stone") to block definitions
+pub struct BlockRegistry {
+    defs: Vec<BlockDef>,
+    by_name: HashMap<String, BlockId>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        let mut registry = Self {
+            defs: Vec::new(),
+            by_name: HashMap::new(),
+        };
+        registry.register(BlockDef::new("minecraft:air", Material::new()));
+        registry
+    }
+
+    pub fn register(&mut self, def: BlockDef) -> BlockId {
+        assert!(!self.by_name.contains_key(&def.name), "block {} registered twice", def.name);
+        assert!(self.defs.len() <= BlockId::MAX as usize, "block registry is full");
+        let id = self.defs.len() as BlockId;
+        self.by_name.insert(def.name.clone(), id);
+        self.defs.push(def);
+        id
+    }
+
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.by_name.get(name).copied()
+    }
+
+    // Like `id`, for names the caller knows are registered
+    pub fn expect_id(&self, name: &str) -> BlockId {
+        self.id(name).unwrap_or_else(|| panic!("unknown block {}", name))
+    }
+
+    pub fn get(&self, id: BlockId) -> &BlockDef {
+        &self.defs[id as usize]
+    }
+    
+    pub fn get_mut(&mut self, id: BlockId) -> &mut BlockDef {
+        &mut self.defs[id as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.defs.len()
+    }
+
+    // Copies every block's face materials into the scene table once
+    pub fn register_materials(&self, table: &mut MaterialTable) -> BlockMaterials {
+        let faces = self
+            .defs
+            .iter()
+            .map(|def| {
+                [BlockFace::Top, BlockFace::Bottom, BlockFace::Side].map(|face| table.add(def.material(face).clone()))
+            })
+            .collect();
+        BlockMaterials { faces }
+    }
+}
+
+// Material IDs of each block face, indexed by BlockId
+#[derive(Clone)]
+pub struct BlockMaterials {
+    faces: Vec<[MaterialId; 3]>,
+}
+
+impl BlockMaterials {
+    pub fn get(&self, id: BlockId, face: BlockFace) -> MaterialId {
+        let faces = &self.faces[id as usize];
+        match face {
+            BlockFace::Top => faces[0],
+            BlockFace::Bottom => faces[1],
+            BlockFace::Side => faces[2],
+        }
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Vanilla-style blocks used by the diorama
+impl BlockRegistry {
+    pub fn minecraft() -> Self {
+        let mut registry = Self::new();
+
+        let grass_top = Material::new()
+            .with_texture(Texture::minecraft_grass())
+            .with_properties(Vec3::new(0.4, 0.8, 0.2), 0.1, 0.0, 0.05);
+        let grass_side = Material::new()
+            .with_texture(Texture::minecraft_grass_side())
+            .with_properties(Vec3::new(0.45, 0.3, 0.15), 0.05, 0.0, 0.02);
+        let dirt = Material::new()
+            .with_texture(Texture::minecraft_dirt())
+            .with_properties(Vec3::new(0.45, 0.3, 0.15), 0.05, 0.0, 0.02);
+        let wood = Material::new()
+            .with_texture(Texture::minecraft_wood())
+            .with_properties(Vec3::new(0.8, 0.5, 0.3), 0.1, 0.0, 0.05);
+
+        registry.register(
+            BlockDef::new("minecraft:grass_block", grass_side)
+                .with_top(grass_top.clone())
+                .with_bottom(dirt.clone()),
+        );
+        registry.register(BlockDef::new("minecraft:dirt", dirt));
+        registry.register(BlockDef::new(
+            "minecraft:stone",
+            Material::new()
+                .with_texture(Texture::minecraft_stone())
+                .with_properties(Vec3::new(0.6, 0.6, 0.6), 0.2, 0.0, 0.1),
+        ));
+        registry.register(BlockDef::new("minecraft:oak_planks", wood.clone()));
+        registry.register(BlockDef::new("minecraft:oak_log", wood));
+        // Leaves reuse the grass texture, as the diorama always has
+        registry.register(BlockDef::new("minecraft:oak_leaves", grass_top));
+        registry.register(BlockDef::new(
+            "minecraft:water",
+            Material::new()
+                .with_texture(Texture::animated_water())
+                .with_properties(Vec3::new(0.2, 0.4, 0.8), 0.9, 0.8, 0.3)
+                .with_refraction(1.33)
+                .with_absorption(Vec3::new(0.9, 0.35, 0.12)),
+        ));
+        registry.register(BlockDef::new(
+            "minecraft:glass",
+            Material::new()
+                .with_texture(Texture::solid_color(Vec3::new(0.9, 0.9, 1.0)))
+                .with_properties(Vec3::new(0.9, 0.9, 1.0), 0.9, 0.9, 0.1)
+                .with_refraction(1.5)
+                .with_absorption(Material::tint_absorption(Vec3::new(0.9, 0.9, 1.0))),
+        ));
+        registry.register(BlockDef::new(
+            "minecraft:diamond_block",
+            Material::new()
+                .with_texture(Texture::minecraft_diamond())
+                .with_properties(Vec3::new(0.7, 0.9, 1.0), 0.95, 0.2, 0.8)
+                .with_refraction(2.4),
+        ));
+        registry.register(BlockDef::new(
+            "minecraft:obsidian",
+            Material::new()
+                .with_texture(Texture::minecraft_obsidian())
+                .with_properties(Vec3::new(0.1, 0.05, 0.2), 0.3, 0.0, 0.6),
+        ));
+        registry.register(
+            BlockDef::new(
+                "minecraft:glowstone",
+                Material::emissive(Vec3::new(1.0, 0.8, 0.4), 2.0).with_texture(Texture::minecraft_glowstone()),
+            )
+            .with_light_level(15),
+        );
+        registry.register(
+            BlockDef::new(
+                "minecraft:nether_portal",
+                Material::new()
+                    .with_texture(Texture::nether_portal())
+                    .with_properties(Vec3::new(0.5, 0.1, 0.8), 0.1, 0.9, 0.3)
+                    .with_emissive(Vec3::new(0.3, 0.1, 0.5))
+                    .with_absorption(Vec3::new(0.7, 2.0, 0.1)),
+            )
+            .with_light_level(11),
+        );
+
+        let fire = Material::emissive(Vec3::new(1.0, 0.4, 0.1), 3.0).with_texture(Texture::animated_fire());
+        registry.register(BlockDef::new("minecraft:campfire", fire.clone()).with_light_level(15));
+        registry.register(BlockDef::new("minecraft:torch", fire).with_light_level(14));
+
+        registry
+    }
+}
@@ -0,0 +1,169 @@
+use crate::math::{fbm, Vec3};
+
+// Soft: fbm-shaped cumulus with rounded tops. Blocky: Minecraft's flat grid of cells.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CloudStyle { Soft, Blocky }
+
+// Procedural cloud slab between `base_height` and `base_height + thickness`, drifting with
+// the wind. The skybox ray-marches it for every sky ray; lights can also query how much of
+// their light gets through it, so clouds cast soft shadows on the terrain.
+#[derive(Clone)]
+pub struct CloudLayer {
+    pub style: CloudStyle,
+    pub base_height: f32,
+    pub thickness: f32,
+    pub coverage: f32, // 0 = clear sky, 1 = overcast
+    pub density: f32,  // Extinction per unit inside a cloud
+    pub scale: f32,    // Feature size (cell size for blocky clouds)
+    pub wind: Vec3,    // Drift per second of scene time
+    pub steps: u32,
+    pub cast_shadows: bool,
+    time: f32,
+    overcast: f32, // Weather pushing the coverage towards a full deck
+}
+
+// Light-march steps towards the sun from each cloud sample, and along terrain shadow rays
+const LIGHT_STEPS: u32 = 3;
+const SHADOW_STEPS: u32 = 3;
+// Lighting and shadows only need the broad shapes, so they skip the fine octaves
+const DETAIL_OCTAVES: i32 = 5;
+const LIGHTING_OCTAVES: i32 = 2;
+// Sky rays are clipped to this distance so grazing ones stay bounded
+const MAX_MARCH_DISTANCE: f32 = 400.0;
+
+impl CloudLayer {
+    pub fn new(style: CloudStyle) -> Self {
+        match style {
+            CloudStyle::Soft => Self {
+                style,
+                base_height: 40.0,
+                thickness: 10.0,
+                coverage: 0.4,
+                density: 0.25,
+                scale: 80.0,
+                wind: Vec3::new(1.5, 0.0, 0.5),
+                steps: 16,
+                cast_shadows: true,
+                time: 0.0,
+                overcast: 0.0,
+            },
+            CloudStyle::Blocky => Self {
+                style,
+                base_height: 45.0,
+                thickness: 4.0,
+                coverage: 0.2,
+                density: 0.5,
+                scale: 12.0,
+                wind: Vec3::new(1.0, 0.0, 0.0),
+                steps: 16,
+                cast_shadows: true,
+                time: 0.0,
+                overcast: 0.0,
+            },
+        }
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    pub fn set_overcast(&mut self, overcast: f32) {
+        self.overcast = overcast.clamp(0.0, 1.0);
+    }
+
+    // Extinction coefficient at `point`
+    pub fn density_at(&self, point: Vec3) -> f32 {
+        self.density_with_octaves(point, DETAIL_OCTAVES)
+    }
+
+    fn density_with_octaves(&self, point: Vec3, octaves: i32) -> f32 {
+        let height = (point.y - self.base_height) / self.thickness;
+        if !(0.0..=1.0).contains(&height) {
+            return 0.0;
+        }
+        let p = point - self.wind * self.time;
+        // Noise above this threshold is cloud; more coverage lowers it
+        let coverage = self.coverage + (1.0 - self.coverage) * self.overcast;
+        let threshold = 0.65 - 0.3 * coverage;
+        match self.style {
+            CloudStyle::Soft => {
+                // Flat-ish bottoms, rounded tops
+                let profile = (height * 4.0).min(1.0) * (1.0 - height * height);
+                let n = fbm(p / self.scale, octaves, 0.5, 2.0) * 0.5 + 0.5; // fbm spans -1..1
+                ((n - threshold) / (1.0 - threshold)).max(0.0) * profile * self.density * 4.0
+            },
+            CloudStyle::Blocky => {
+                let cell = Vec3::new((p.x / self.scale).floor(), 0.0, (p.z / self.scale).floor());
+                if fbm(cell * 0.4, 3, 0.5, 2.0) * 0.5 + 0.5 > threshold { self.density } else { 0.0 }
+            },
+        }
+    }
+
+    // Distances along `direction` from `origin` where the ray is inside the slab
+    fn slab_span(&self, origin: Vec3, direction: Vec3) -> Option<(f32, f32)> {
+        if direction.y.abs() < 1e-4 {
+            return None;
+        }
+        let t_bottom = (self.base_height - origin.y) / direction.y;
+        let t_top = (self.base_height + self.thickness - origin.y) / direction.y;
+        let (t_near, t_far) = (t_bottom.min(t_top).max(0.0), t_bottom.max(t_top).min(MAX_MARCH_DISTANCE));
+        if t_near < t_far { Some((t_near, t_far)) } else { None }
+    }
+
+    // Fraction of light from `light_dir` reaching `point` through the clouds above it
+    pub fn transmittance(&self, point: Vec3, light_dir: Vec3) -> f32 {
+        let (t_near, t_far) = match self.slab_span(point, light_dir) {
+            Some(span) if light_dir.y > 0.0 => span,
+            _ => return 1.0,
+        };
+        let step = (t_far - t_near) / SHADOW_STEPS as f32;
+        let optical_depth: f32 = (0..SHADOW_STEPS)
+            .map(|i| self.density_with_octaves(point + light_dir * (t_near + (i as f32 + 0.5) * step), LIGHTING_OCTAVES) * step)
+            .sum();
+        (-optical_depth).exp()
+    }
+
+    // Single scattering along a sky ray from the world origin: returns the light the clouds
+    // send towards the viewer and the fraction of the sky behind them that stays visible.
+    // `light` is the sun (or moon) radiance and `ambient` the sky light on their shaded sides.
+    pub fn march(&self, direction: Vec3, light_dir: Vec3, light: Vec3, ambient: Vec3) -> (Vec3, f32) {
+        let (t_near, t_far) = match self.slab_span(Vec3::zero(), direction) {
+            Some(span) if direction.y > 0.0 => span,
+            _ => return (Vec3::zero(), 1.0),
+        };
+        // Thin out towards the horizon, where the slab is seen edge-on
+        let fade = Vec3::smoothstep(0.02, 0.12, direction.y);
+        let step = (t_far - t_near) / self.steps as f32;
+        let phase = dual_lobe_phase(direction.dot(light_dir));
+        let light_step = self.thickness / LIGHT_STEPS as f32;
+
+        let mut transmittance = 1.0;
+        let mut scattered = Vec3::zero();
+        for i in 0..self.steps {
+            let point = direction * (t_near + (i as f32 + 0.5) * step);
+            let density = self.density_at(point) * fade;
+            if density <= 0.0 {
+                continue;
+            }
+            let light_depth: f32 = (1..=LIGHT_STEPS)
+                .map(|j| self.density_with_octaves(point + light_dir * (j as f32 * light_step), LIGHTING_OCTAVES) * light_step)
+                .sum();
+            // Beer's law plus a softer term standing in for multiple scattering
+            let sun_visibility = (-light_depth).exp() + 0.3 * (-light_depth * 0.25).exp();
+            let in_scatter = light * (sun_visibility * phase) + ambient;
+            let step_transmittance = (-density * step).exp();
+            scattered = scattered + in_scatter * (transmittance * (1.0 - step_transmittance));
+            transmittance *= step_transmittance;
+            if transmittance < 0.01 {
+                break;
+            }
+        }
+        (scattered, transmittance)
+    }
+}
+
+// Forward-scattering silver lining blended with a broad back lobe (Henyey–Greenstein)
+fn dual_lobe_phase(cos_theta: f32) -> f32 {
+    let hg = |g: f32| (1.0 - g * g) / (4.0 * std::f32::consts::PI * (1.0 + g * g - 2.0 * g * cos_theta).max(1e-4).powf(1.5));
+    0.7 * hg(0.45) + 0.3 * hg(-0.3)
+}
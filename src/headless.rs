@@ -0,0 +1,337 @@
+use crate::math::Vec3;
+use crate::raytracer::{trace_ray, Camera, Integrator, RenderOptions, Scene, ShadowMode};
+use crate::clouds::CloudStyle;
+use crate::weather::{Weather, WeatherKind};
+use crate::accumulation::Accumulator;
+use crate::alloc_counter::AllocStats;
+use rayon::prelude::*;
+use std::time::Instant;
+
+pub const USAGE: &str = "\
+Headless rendering (no window):
+  --headless               Render to PNG files and exit
+  --size WxH               Output resolution (default 800x600)
+  --camera X,Y,Z           Camera position (default 15,10,15)
+  --target X,Y,Z           Point the camera looks at (default 0,0,0)
+  --fov DEGREES            Vertical field of view (default 45)
+  --time SECONDS           Scene time; drives the day/night cycle (default 0)
+  --day-speed SPEED        Day/night cycle speed (default 0.15)
+  --shadows none|sun|full  Shadow mode (default full)
+  --max-depth N            Maximum ray depth (default 4)
+  --integrator whitted|path  Direct lighting only, or path-traced global illumination (default whitted)
+  --volumetric             Ray-march the volumetric fog (light shafts, haze around lights)
+  --clouds none|soft|blocky  Cloud layer in the sky (default soft)
+  --weather auto|clear|rain|snow|thunder  Weather; auto changes it every minute of scene time (default auto)
+  --samples N              Jittered samples accumulated per pixel (default 1)
+  --aperture R             Lens radius for depth of field, focused on the target (default 0)
+  --frames N               Render an N-frame sequence (default 1)
+  --fps N                  Scene time step between frames is 1/N (default 24)
+  --benchmark              Time the frames and count heap allocations instead of saving PNGs
+  --output PATH            PNG path; sequences insert _0000, _0001, ... (default render.png)";
+
+#[derive(Clone)]
+pub struct HeadlessOptions {
+    pub width: usize,
+    pub height: usize,
+    pub camera_position: Vec3,
+    pub camera_target: Vec3,
+    pub fov: f32,
+    pub time: f32,
+    pub day_speed: f32,
+    pub shadow_mode: ShadowMode,
+    pub max_depth: i32,
+    pub integrator: Integrator,
+    pub volumetric: bool,
+    pub clouds: Option<CloudStyle>,
+    pub weather: Weather,
+    pub samples: u32,
+    pub aperture: f32,
+    pub frames: u32,
+    pub fps: f32,
+    pub output: String,
+    pub benchmark: bool,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            camera_position: Vec3::new(15.0, 10.0, 15.0),
+            camera_target: Vec3::zero(),
+            fov: 45.0,
+            time: 0.0,
+            day_speed: 0.15,
+            shadow_mode: ShadowMode::Full,
+            max_depth: 4,
+            integrator: Integrator::Whitted,
+            volumetric: false,
+            clouds: Some(CloudStyle::Soft),
+            weather: Weather::Auto,
+            samples: 1,
+            aperture: 0.0,
+            frames: 1,
+            fps: 24.0,
+            output: "render.png".to_string(),
+            benchmark: false,
+        }
+    }
+}
+
+impl HeadlessOptions {
+    // None unless `--headless` is among the arguments. Flags parsed by the rest of the
+    // program (`--resource-pack`, `--bvh*`) are skipped; any other unknown one is an error
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        if !args.iter().any(|a| a == "--headless") {
+            return Ok(None);
+        }
+        let mut opts = Self::default();
+        let mut i = 1;
+        while i < args.len() {
+            let flag = args[i].as_str();
+            let takes_value = !matches!(flag, "--headless" | "--benchmark" | "--volumetric" | "--bvh-stats");
+            let value = if takes_value { args.get(i + 1).map(|v| v.as_str()) } else { None };
+            let need = || value.ok_or_else(|| format!("{} needs a value", flag));
+            match flag {
+                "--headless" => {},
+                "--benchmark" => opts.benchmark = true,
+                "--volumetric" => opts.volumetric = true,
+                "--size" => {
+                    let (w, h) = need()?.split_once('x').ok_or("--size expects WxH")?;
+                    opts.width = parse_number(flag, w)?;
+                    opts.height = parse_number(flag, h)?;
+                    if opts.width == 0 || opts.height == 0 {
+                        return Err("--size must be non-zero".to_string());
+                    }
+                },
+                "--camera" => opts.camera_position = parse_vec3(flag, need()?)?,
+                "--target" => opts.camera_target = parse_vec3(flag, need()?)?,
+                "--fov" => {
+                    opts.fov = parse_number(flag, need()?)?;
+                    if !(opts.fov > 0.0 && opts.fov < 180.0) {
+                        return Err("--fov must be between 0 and 180 degrees".to_string());
+                    }
+                },
+                "--time" => opts.time = parse_number(flag, need()?)?,
+                "--day-speed" => opts.day_speed = parse_number(flag, need()?)?,
+                "--shadows" => {
+                    opts.shadow_mode = match need()? {
+                        "none" => ShadowMode::None,
+                        "sun" => ShadowMode::SunOnly,
+                        "full" => ShadowMode::Full,
+                        other => return Err(format!("unknown shadow mode {} (none, sun, full)", other)),
+                    }
+                },
+                "--max-depth" => {
+                    opts.max_depth = parse_number(flag, need()?)?;
+                    if opts.max_depth < 1 {
+                        return Err("--max-depth must be at least 1".to_string());
+                    }
+                },
+                "--integrator" => {
+                    opts.integrator = match need()? {
+                        "whitted" => Integrator::Whitted,
+                        "path" => Integrator::PathTraced,
+                        other => return Err(format!("unknown integrator {} (whitted, path)", other)),
+                    }
+                },
+                "--clouds" => {
+                    opts.clouds = match need()? {
+                        "none" => None,
+                        "soft" => Some(CloudStyle::Soft),
+                        "blocky" => Some(CloudStyle::Blocky),
+                        other => return Err(format!("unknown cloud style {} (none, soft, blocky)", other)),
+                    }
+                },
+                "--weather" => {
+                    opts.weather = match need()? {
+                        "auto" => Weather::Auto,
+                        "clear" => Weather::Fixed(WeatherKind::Clear),
+                        "rain" => Weather::Fixed(WeatherKind::Rain),
+                        "snow" => Weather::Fixed(WeatherKind::Snow),
+                        "thunder" => Weather::Fixed(WeatherKind::Thunder),
+                        other => return Err(format!("unknown weather {} (auto, clear, rain, snow, thunder)", other)),
+                    }
+                },
+                "--samples" => opts.samples = parse_number::<u32>(flag, need()?)?.max(1),
+                "--aperture" => opts.aperture = parse_number(flag, need()?)?,
+                "--frames" => opts.frames = parse_number::<u32>(flag, need()?)?.max(1),
+                "--fps" => {
+                    opts.fps = parse_number(flag, need()?)?;
+                    if opts.fps <= 0.0 {
+                        return Err("--fps must be positive".to_string());
+                    }
+                },
+                "--output" => opts.output = need()?.to_string(),
+                "--resource-pack" | "--bvh" | "--bvh-leaf-size" | "--bvh-stats" => {},
+                other => return Err(format!("unknown argument {}", other)),
+            }
+            i += if takes_value { 2 } else { 1 };
+        }
+        Ok(Some(opts))
+    }
+
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            shadow_mode: self.shadow_mode,
+            max_depth: self.max_depth,
+            far_simplify_distance: 20.0,
+            integrator: self.integrator,
+            volumetric: self.volumetric,
+        }
+    }
+
+    pub fn frame_path(&self, frame: u32) -> String {
+        if self.frames <= 1 {
+            return self.output.clone();
+        }
+        match self.output.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() && !ext.contains('/') => format!("{}_{:04}.{}", stem, frame, ext),
+            _ => format!("{}_{:04}.png", self.output, frame),
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn parse_vec3(flag: &str, value: &str) -> Result<Vec3, String> {
+    let parts: Vec<f32> = value.split(',').map(|p| parse_number(flag, p)).collect::<Result<_, _>>()?;
+    match parts[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("{} expects X,Y,Z", flag)),
+    }
+}
+
+// Same per-pixel work as the windowed renderer, at an arbitrary resolution; more than
+// one sample goes through the progressive accumulator
+pub fn render_image(scene: &Scene, camera: &Camera, width: usize, height: usize, time: f32, samples: u32, opts: &RenderOptions) -> image::RgbImage {
+    if samples > 1 || camera.aperture > 0.0 {
+        let mut accumulator = Accumulator::new(width, height);
+        while accumulator.samples() < samples {
+            accumulator.add_samples(scene, camera, time, 0.0, opts);
+        }
+        let mut buffer = vec![0u32; width * height];
+        accumulator.resolve(&mut buffer);
+        let pixels = buffer.iter().flat_map(|rgb| [(rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8]).collect();
+        return image::RgbImage::from_raw(width as u32, height as u32, pixels).expect("buffer matches image size");
+    }
+
+    let frame = camera.build_frame(width, height);
+    let mut pixels = vec![0u8; width * height * 3];
+    pixels.par_chunks_mut(width * 3).enumerate().for_each(|(y, row)| {
+        for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+            let ray = frame.get_ray(x as f32, y as f32);
+            let rgb = crate::color_to_u32(trace_ray(&ray, scene, 0, time, 0.0, opts));
+            pixel.copy_from_slice(&[(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]);
+        }
+    });
+    image::RgbImage::from_raw(width as u32, height as u32, pixels).expect("buffer matches image size")
+}
+
+// Renders the requested frame(s); the scene is animated with the same update as the window loop
+pub fn run(opts: &HeadlessOptions, scene: &mut Scene) -> Result<(), Box<dyn std::error::Error>> {
+    let camera = Camera::new(
+        opts.camera_position,
+        opts.camera_target,
+        Vec3::new(0.0, 1.0, 0.0),
+        opts.fov,
+        opts.width as f32 / opts.height as f32,
+    );
+    let camera = if opts.aperture > 0.0 {
+        let focus = (opts.camera_target - opts.camera_position).length();
+        camera.with_depth_of_field(focus, opts.aperture)
+    } else {
+        camera
+    };
+    let render_options = opts.render_options();
+    if let Some(skybox) = &mut scene.skybox {
+        skybox.set_cloud_style(opts.clouds);
+    }
+
+    let mut total_ms = 0.0;
+    let mut total_allocs = AllocStats::default();
+    for frame in 0..opts.frames {
+        let time = opts.time + frame as f32 / opts.fps;
+        crate::update_minecraft_scene(scene, time, opts.day_speed, opts.weather);
+
+        let start = Instant::now();
+        let allocs_before = AllocStats::now();
+        let image = render_image(scene, &camera, opts.width, opts.height, time, opts.samples, &render_options);
+        let allocs = AllocStats::since(allocs_before);
+        let ms = start.elapsed().as_secs_f32() * 1000.0;
+
+        if opts.benchmark {
+            println!("Frame {}/{}: {:.0}ms, {} allocations ({:.1} KiB)", frame + 1, opts.frames, ms, allocs.allocations, allocs.bytes as f32 / 1024.0);
+            total_ms += ms;
+            total_allocs.allocations += allocs.allocations;
+            total_allocs.bytes += allocs.bytes;
+        } else {
+            let path = opts.frame_path(frame);
+            image.save(&path).map_err(|e| format!("{}: {}", path, e))?;
+            println!("Frame {}/{} -> {} ({:.0}ms)", frame + 1, opts.frames, path, ms);
+        }
+    }
+    if opts.benchmark {
+        let n = opts.frames as f32;
+        println!(
+            "Average over {} frames: {:.1}ms, {:.0} allocations ({:.1} KiB) per frame",
+            opts.frames, total_ms / n, total_allocs.allocations as f32 / n, total_allocs.bytes as f32 / 1024.0 / n
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn parse(line: &str) -> Result<Option<HeadlessOptions>, String> {
+        let args: Vec<String> = std::iter::once("minecraft").chain(line.split_whitespace()).map(String::from).collect();
+        HeadlessOptions::from_args(&args)
+    }
+    
+    fn rejection(line: &str) -> String {
+        parse(line).err().unwrap_or_else(|| panic!("`{line}` should be rejected"))
+    }
+    
+    #[test]
+    fn windowed_runs_have_no_headless_options() {
+        assert!(parse("--bvh median").unwrap().is_none());
+    }
+    
+    #[test]
+    fn parses_values_and_skips_flags_handled_elsewhere() {
+        let opts = parse("--headless --bvh median --bvh-stats --size 320x240 --fov 60 --camera 1,2,3 --shadows sun").unwrap().unwrap();
+        assert_eq!((opts.width, opts.height), (320, 240));
+        assert_eq!(opts.fov, 60.0);
+        assert_eq!(opts.camera_position.z, 3.0);
+        assert!(opts.shadow_mode == ShadowMode::SunOnly);
+    }
+    
+    #[test]
+    fn rejects_unknown_flags_and_missing_values() {
+        assert_eq!(rejection("--headless --fast"), "unknown argument --fast");
+        assert_eq!(rejection("--headless --output"), "--output needs a value");
+        assert_eq!(rejection("--headless --shadows soft"), "unknown shadow mode soft (none, sun, full)");
+    }
+    
+    #[test]
+    fn rejects_malformed_values() {
+        assert_eq!(rejection("--headless --size 800"), "--size expects WxH");
+        assert_eq!(rejection("--headless --size 0x600"), "--size must be non-zero");
+        assert_eq!(rejection("--headless --camera 1,2"), "--camera expects X,Y,Z");
+        assert_eq!(rejection("--headless --time soon"), "invalid value for --time: soon");
+    }
+    
+    #[test]
+    fn rejects_out_of_range_values() {
+        assert_eq!(rejection("--headless --max-depth 0"), "--max-depth must be at least 1");
+        assert_eq!(rejection("--headless --fps 0"), "--fps must be positive");
+        for fov in ["0", "-10", "180", "270", "NaN"] {
+            assert_eq!(rejection(&format!("--headless --fov {fov}")), "--fov must be between 0 and 180 degrees");
+        }
+        assert_eq!(parse("--headless --fov 179.5").unwrap().unwrap().fov, 179.5);
+    }
+}
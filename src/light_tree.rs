@@ -0,0 +1,201 @@
+use crate::math::Vec3;
+use crate::raytracer::{distance_attenuation, Light};
+
+// Which list a tree leaf points into
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightRef {
+    Scene(usize),   // scene.lights
+    Emitter(usize), // scene.emitters
+}
+
+#[derive(Clone, Copy)]
+enum LightNodeKind {
+    Leaf(LightRef),
+    Inner(usize, usize),
+}
+
+struct LightNode {
+    bounds_min: Vec3,
+    bounds_max: Vec3,
+    power: f32,
+    kind: LightNodeKind,
+}
+
+// Binary tree over positional lights, each node holding the bounds and total power of
+// the lights below it. Sampling walks one root-to-leaf path, choosing children by
+// power and distance, so picking a light costs O(log n) whatever the light count.
+#[derive(Default)]
+pub struct LightTree {
+    nodes: Vec<LightNode>,
+}
+
+struct LightEntry {
+    light: LightRef,
+    bounds_min: Vec3,
+    bounds_max: Vec3,
+    power: f32,
+}
+
+impl LightEntry {
+    fn centroid(&self) -> Vec3 {
+        (self.bounds_min + self.bounds_max) * 0.5
+    }
+}
+
+impl LightTree {
+    // Directional lights and lights without power are left out
+    pub fn build(lights: &[Light], emitters: &[Light]) -> Self {
+        let scene = lights.iter().enumerate().map(|(i, light)| (LightRef::Scene(i), light));
+        let extracted = emitters.iter().enumerate().map(|(i, light)| (LightRef::Emitter(i), light));
+        let mut entries: Vec<LightEntry> = scene
+            .chain(extracted)
+            .filter(|(_, light)| !light.light_type.is_directional() && light.power() > 0.0)
+            .map(|(light_ref, light)| {
+                let (bounds_min, bounds_max) = light.bounds();
+                LightEntry { light: light_ref, bounds_min, bounds_max, power: light.power() }
+            })
+            .collect();
+
+        let mut tree = Self { nodes: Vec::with_capacity(entries.len() * 2) };
+        if !entries.is_empty() {
+            tree.build_recursive(&mut entries);
+        }
+        tree
+    }
+
+    fn build_recursive(&mut self, entries: &mut [LightEntry]) -> usize {
+        let mut bounds_min = entries[0].bounds_min;
+        let mut bounds_max = entries[0].bounds_max;
+        for entry in entries.iter() {
+            bounds_min = bounds_min.min(entry.bounds_min);
+            bounds_max = bounds_max.max(entry.bounds_max);
+        }
+        let power = entries.iter().map(|e| e.power).sum();
+
+        let index = self.nodes.len();
+        if entries.len() == 1 {
+            self.nodes.push(LightNode { bounds_min, bounds_max, power, kind: LightNodeKind::Leaf(entries[0].light) });
+            return index;
+        }
+
+        // Median split along the widest axis of the centroids
+        let extent = bounds_max - bounds_min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        let key = |e: &LightEntry| match axis {
+            0 => e.centroid().x,
+            1 => e.centroid().y,
+            _ => e.centroid().z,
+        };
+        entries.sort_by(|a, b| key(a).total_cmp(&key(b)));
+
+        self.nodes.push(LightNode { bounds_min, bounds_max, power, kind: LightNodeKind::Leaf(entries[0].light) });
+        let mid = entries.len() / 2;
+        let (left, right) = entries.split_at_mut(mid);
+        let left = self.build_recursive(left);
+        let right = self.build_recursive(right);
+        self.nodes[index].kind = LightNodeKind::Inner(left, right);
+        index
+    }
+
+    // Light chosen by `u` in [0, 1) for shading `point`, with the probability of choosing it
+    pub fn sample(&self, point: Vec3, mut u: f32) -> Option<(LightRef, f32)> {
+        let mut node = self.nodes.first()?;
+        let mut probability = 1.0;
+        loop {
+            match node.kind {
+                LightNodeKind::Leaf(light) => return Some((light, probability)),
+                LightNodeKind::Inner(left, right) => {
+                    let (left, right) = (&self.nodes[left], &self.nodes[right]);
+                    let (left_weight, right_weight) = (left.importance(point), right.importance(point));
+                    let total = left_weight + right_weight;
+                    if total <= 0.0 {
+                        return None;
+                    }
+                    let p_left = left_weight / total;
+                    if u < p_left {
+                        u /= p_left;
+                        probability *= p_left;
+                        node = left;
+                    } else {
+                        u = ((u - p_left) / (1.0 - p_left)).min(0.999_999);
+                        probability *= 1.0 - p_left;
+                        node = right;
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl LightNode {
+    // Power over the same falloff the lights use, from the nearest point of the bounds
+    fn importance(&self, point: Vec3) -> f32 {
+        let nearest = point.max(self.bounds_min).min(self.bounds_max);
+        let distance = (nearest - point).length();
+        self.power * distance_attenuation(distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lights() -> Vec<Light> {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        vec![
+            Light::point(Vec3::new(0.0, 2.0, 0.0), white, 1.0),
+            Light::point(Vec3::new(8.0, 2.0, 1.0), white, 3.0),
+            Light::point(Vec3::new(-5.0, 6.0, 4.0), white, 0.5),
+            Light::directional(Vec3::new(0.0, -1.0, 0.0), white, 2.0),
+            Light::point(Vec3::new(3.0, 1.0, -7.0), white, 2.0),
+        ]
+    }
+
+    // Every leaf with the probability of walking down to it from `point`
+    fn leaf_probabilities(tree: &LightTree, point: Vec3) -> Vec<(LightRef, f32)> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![(0, 1.0)];
+        while let Some((index, probability)) = stack.pop() {
+            match tree.nodes[index].kind {
+                LightNodeKind::Leaf(light) => leaves.push((light, probability)),
+                LightNodeKind::Inner(left, right) => {
+                    let left_weight = tree.nodes[left].importance(point);
+                    let p_left = left_weight / (left_weight + tree.nodes[right].importance(point));
+                    stack.push((left, probability * p_left));
+                    stack.push((right, probability * (1.0 - p_left)));
+                },
+            }
+        }
+        leaves
+    }
+
+    #[test]
+    fn pdfs_over_all_lights_sum_to_one() {
+        let emitters = [Light::point(Vec3::new(1.0, 0.5, 1.0), Vec3::new(1.0, 0.5, 0.2), 1.5)];
+        let tree = LightTree::build(&lights(), &emitters);
+        for point in [Vec3::zero(), Vec3::new(7.0, 0.0, 1.0), Vec3::new(-20.0, 3.0, 15.0)] {
+            let leaves = leaf_probabilities(&tree, point);
+            // Four positional scene lights and the emitter; the directional light stays out
+            assert_eq!(leaves.len(), 5);
+            assert!(leaves.iter().all(|&(light, _)| light != LightRef::Scene(3)));
+            let total: f32 = leaves.iter().map(|&(_, probability)| probability).sum();
+            assert!((total - 1.0).abs() < 1e-5, "total {total} at {point:?}");
+
+            // Sampling reports the same probability as the walk
+            for i in 0..64 {
+                let (light, probability) = tree.sample(point, (i as f32 + 0.5) / 64.0).unwrap();
+                let &(_, expected) = leaves.iter().find(|&&(leaf, _)| leaf == light).unwrap();
+                assert!((probability - expected).abs() < 1e-5, "{light:?}: {probability} vs {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn single_light_is_sampled_with_probability_one() {
+        let light = [Light::point(Vec3::new(2.0, 3.0, 4.0), Vec3::new(1.0, 1.0, 1.0), 1.0)];
+        let tree = LightTree::build(&[], &light);
+        for u in [0.0, 0.5, 0.999] {
+            assert_eq!(tree.sample(Vec3::zero(), u), Some((LightRef::Emitter(0), 1.0)));
+        }
+    }
+}
@@ -1,9 +1,25 @@
+// The diorama carries a toolbox of primitives, material presets and texture generators
+// that only some scenes use; unused ones are allowed rather than deleted
+#![allow(dead_code)]
+
 mod math;
 mod raytracer;
 mod materials;
 mod primitives;
 mod texture;
 mod obj_loader;
+mod voxel;
+mod blocks;
+mod resource_pack;
+mod headless;
+mod accumulation;
+mod alloc_counter;
+mod light_tree;
+mod volumetric;
+mod sky;
+mod clouds;
+mod weather;
+mod particles;
 
 use minifb::{Key, Window, WindowOptions};
 use rayon::prelude::*;
@@ -13,12 +29,51 @@ use raytracer::*;
 use math::*;
 use materials::*;
 use primitives::*;
-use texture::*;
 use obj_loader::*;
+use voxel::*;
+use blocks::*;
+use resource_pack::ResourcePack;
+use volumetric::VolumetricFog;
+use clouds::{CloudLayer, CloudStyle};
+use weather::{Weather, WeatherKind};
+use particles::{ParticleEmitter, ParticleShape};
+use std::sync::Arc;
+
+#[global_allocator]
+static ALLOCATOR: alloc_counter::CountingAllocator = alloc_counter::CountingAllocator;
 
 const WIDTH: usize = 800;
 const HEIGHT: usize = 600;
 
+const USAGE: &str = "Usage: minecraft [--resource-pack DIR] [--bvh median|sah] [--bvh-leaf-size N] [--bvh-stats] [--headless ...]";
+
+// Half-extent of the generated terrain in blocks (the diorama spans 2x this per side)
+const TERRAIN_HALF_SIZE: i32 = 15;
+
+// Lens radius for depth of field while accumulating (O key)
+const DOF_APERTURE: f32 = 0.4;
+
+// Apparent size of the sun in degrees; larger than the real 0.53 for visibly soft shadows
+const SUN_ANGULAR_DIAMETER: f32 = 3.0;
+
+// Sun light intensity above the atmosphere; the sky model tints and dims it
+const SUN_INTENSITY: f32 = 2.5;
+
+// Moonlight at full moon; fades with the phase and whenever the sun is up
+const MOON_INTENSITY: f32 = 0.35;
+
+// Atmospheric haze for the sky model (2 = very clear, 10 = hazy)
+const SKY_TURBIDITY: f32 = 3.0;
+
+// Volumetric fog extinction at ground level at noon and midnight (V key)
+const FOG_DAY_DENSITY: f32 = 0.015;
+const FOG_NIGHT_DENSITY: f32 = 0.045;
+
+// Haze in rain and snow, how much overcast skies dim the sun, and a lightning flash's peak
+const WEATHER_HAZE_DENSITY: f32 = 0.02;
+const OVERCAST_SUN_DIMMING: f32 = 0.75;
+const LIGHTNING_INTENSITY: f32 = 4.0;
+
 #[derive(Clone, Copy)]
 struct RenderState {
     scale_factor: usize, // 1 = full res, 2 = half res, etc.
@@ -26,14 +81,41 @@ struct RenderState {
     max_depth: i32,
     ultra_mode: bool,
     checker_phase: bool,
+    depth_of_field: bool, // Only applied to still-camera accumulation
+    path_tracing: bool,
+    volumetric: bool,
+    clouds: Option<CloudStyle>,
+    weather: Weather,
 }
 
 fn main() {
-    let mut window_options = WindowOptions::default();
-    window_options.scale = minifb::Scale::X2;
-    window_options.resize = true;
-    window_options.title = true;
-    window_options.borderless = false;
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}\n\n{}", USAGE, headless::USAGE);
+        return;
+    }
+    let headless_options = headless::HeadlessOptions::from_args(&args).unwrap_or_else(|e| exit_with_usage(&e));
+    let bvh_config = bvh_config_from_args(&args).unwrap_or_else(|e| exit_with_usage(&e));
+    let bvh_stats = args.iter().any(|a| a == "--bvh-stats");
+    let registry = block_registry_from_args(&args);
+    
+    if let Some(opts) = headless_options {
+        let mut scene = create_minecraft_scene(registry);
+        build_scene_bvh(&mut scene, &bvh_config, bvh_stats);
+        if let Err(e) = headless::run(&opts, &mut scene) {
+            eprintln!("Headless render failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    
+    let window_options = WindowOptions {
+        scale: minifb::Scale::X2,
+        resize: true,
+        title: true,
+        borderless: false,
+        ..WindowOptions::default()
+    };
     
     let mut window = Window::new(
         "Minecraft Raytracing Diorama - WASD: Move, Mouse: Look, R: Rotate, Scroll: Zoom, T: Toggle Day/Night",
@@ -41,7 +123,7 @@ fn main() {
         HEIGHT,
         window_options,
     ).unwrap_or_else(|e| panic!("{}", e));
-    let _ = window.set_position(100, 100);
+    window.set_position(100, 100);
     
     window.limit_update_rate(Some(std::time::Duration::from_micros(16666))); // 60 FPS
 
@@ -54,9 +136,9 @@ fn main() {
         WIDTH as f32 / HEIGHT as f32,
     );
 
-    let mut scene = create_minecraft_scene();
+    let mut scene = create_minecraft_scene(registry);
     // Build BVH once after scene creation for faster ray intersections
-    build_scene_bvh(&mut scene);
+    build_scene_bvh(&mut scene, &bvh_config, bvh_stats);
     let mut frame_buffer = vec![0u32; WIDTH * HEIGHT];
     let mut prev_full_buffer = vec![0u32; WIDTH * HEIGHT];
     let mut lowres_buffer: Vec<u32> = Vec::new();
@@ -65,8 +147,10 @@ fn main() {
     let mut fps_counter = 0;
     let mut fps_timer = Instant::now();
     let mut rotation_y = 0.0f32;
-    let mut input_state = InputState::default();
+    let mut input_state = InputState::new();
     let mut manual_time_control = false;
+    let mut accumulator = accumulation::Accumulator::new(WIDTH, HEIGHT);
+    let mut last_scene_state = None;
 
     println!("=== Minecraft Raytracer Controls ===");
     println!("WASD/Arrow Keys: Move camera");
@@ -76,13 +160,18 @@ fn main() {
     println!("1-4: Resolution scale, Y/U/I: Shadows None/SunOnly/Full, F/G: Max depth +/-");
     println!("N/M: Day-Night speed -/+ (más rápido o más lento)");
     println!("Z: Ultra mode (checkerboard + temporal reuse)");
+    println!("O: Depth of field (visible once the camera is still)");
+    println!("P: Path-traced global illumination (converges once the camera is still)");
+    println!("V: Volumetric fog (light shafts)");
+    println!("C: Clouds soft/blocky/off");
+    println!("B: Weather auto/clear/rain/snow/thunder");
     println!("Mouse: Look around (drag)");
     println!("Scroll: Zoom in/out");
     println!("ESC: Exit");
     println!("====================================");
 
     // Faster defaults for smoother movement (adjust at runtime with keys above)
-    let mut render_state = RenderState { scale_factor: 3, shadow_mode: raytracer::ShadowMode::None, max_depth: 2, ultra_mode: true, checker_phase: false };
+    let mut render_state = RenderState { scale_factor: 3, shadow_mode: raytracer::ShadowMode::None, max_depth: 2, ultra_mode: true, checker_phase: false, depth_of_field: false, path_tracing: false, volumetric: false, clouds: Some(CloudStyle::Soft), weather: Weather::Auto };
     let mut day_speed: f32 = 0.15;
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
@@ -90,7 +179,7 @@ fn main() {
         
         let delta_time = 1.0 / 60.0;
         input_state.move_speed = 5.0 * delta_time;
-        handle_input(&window, &mut camera, &mut rotation_y, &mut input_state, &mut manual_time_control);
+        let view_changed = handle_input(&window, &mut camera, &mut rotation_y, &mut input_state, &mut manual_time_control);
         
         if manual_time_control {
             time += 0.016;
@@ -105,7 +194,29 @@ fn main() {
                 println!("Toggled day/night");
             }
         }
-        update_minecraft_scene(&mut scene, time, day_speed);
+        // Before the scene update so a new cloud layer starts at the current drift
+        if window.is_key_pressed(Key::C, minifb::KeyRepeat::No) {
+            render_state.clouds = match render_state.clouds {
+                Some(CloudStyle::Soft) => Some(CloudStyle::Blocky),
+                Some(CloudStyle::Blocky) => None,
+                None => Some(CloudStyle::Soft),
+            };
+            if let Some(skybox) = &mut scene.skybox {
+                skybox.set_cloud_style(render_state.clouds);
+            }
+            println!("Clouds: {}", match render_state.clouds { Some(CloudStyle::Soft) => "Soft", Some(CloudStyle::Blocky) => "Blocky", None => "OFF" });
+        }
+        if window.is_key_pressed(Key::B, minifb::KeyRepeat::No) {
+            render_state.weather = match render_state.weather {
+                Weather::Auto => Weather::Fixed(WeatherKind::Clear),
+                Weather::Fixed(WeatherKind::Clear) => Weather::Fixed(WeatherKind::Rain),
+                Weather::Fixed(WeatherKind::Rain) => Weather::Fixed(WeatherKind::Snow),
+                Weather::Fixed(WeatherKind::Snow) => Weather::Fixed(WeatherKind::Thunder),
+                Weather::Fixed(WeatherKind::Thunder) => Weather::Auto,
+            };
+            println!("Weather: {:?}", render_state.weather);
+        }
+        update_minecraft_scene(&mut scene, time, day_speed, render_state.weather);
         
         let render_start = Instant::now();
         // Keyboard toggles for performance/quality
@@ -121,10 +232,34 @@ fn main() {
         if window.is_key_pressed(Key::Z, minifb::KeyRepeat::No) { render_state.ultra_mode = !render_state.ultra_mode; println!("Ultra mode: {}", if render_state.ultra_mode { "ON" } else { "OFF" }); }
         if window.is_key_pressed(Key::N, minifb::KeyRepeat::No) { day_speed = (day_speed - 0.05).max(0.02); println!("Day speed: {:.2}", day_speed); }
         if window.is_key_pressed(Key::M, minifb::KeyRepeat::No) { day_speed = (day_speed + 0.05).min(1.0); println!("Day speed: {:.2}", day_speed); }
+        if window.is_key_pressed(Key::O, minifb::KeyRepeat::No) { render_state.depth_of_field = !render_state.depth_of_field; println!("Depth of field: {}", if render_state.depth_of_field { "ON" } else { "OFF" }); }
+        if window.is_key_pressed(Key::P, minifb::KeyRepeat::No) { render_state.path_tracing = !render_state.path_tracing; println!("Path tracing: {}", if render_state.path_tracing { "ON" } else { "OFF" }); }
+        if window.is_key_pressed(Key::V, minifb::KeyRepeat::No) { render_state.volumetric = !render_state.volumetric; println!("Volumetric fog: {}", if render_state.volumetric { "ON" } else { "OFF" }); }
         
-        let opts = raytracer::RenderOptions { shadow_mode: render_state.shadow_mode, max_depth: render_state.max_depth, far_simplify_distance: 20.0 };
+        let integrator = if render_state.path_tracing { raytracer::Integrator::PathTraced } else { raytracer::Integrator::Whitted };
+        let opts = raytracer::RenderOptions { shadow_mode: render_state.shadow_mode, max_depth: render_state.max_depth, far_simplify_distance: 20.0, integrator, volumetric: render_state.volumetric };
         
-        if render_state.ultra_mode {
+        // Accumulate jittered samples while nothing moves; any change starts over
+        let scene_state = Some((time, day_speed, opts, render_state.depth_of_field, render_state.clouds, render_state.weather));
+        let still = !view_changed && scene_state == last_scene_state;
+        if !still {
+            accumulator.reset();
+            last_scene_state = scene_state;
+        }
+        
+        if still {
+            if !accumulator.is_converged() {
+                let sample_camera = if render_state.depth_of_field {
+                    // Focus on the look-at target
+                    let focus = (camera.target - camera.position).length();
+                    camera.clone().with_depth_of_field(focus, DOF_APERTURE)
+                } else {
+                    camera.clone()
+                };
+                accumulator.add_samples(&scene, &sample_camera, time, rotation_y, &opts);
+                accumulator.resolve(&mut frame_buffer);
+            }
+        } else if render_state.ultra_mode {
             render_checkerboard_scaled(
                 &scene,
                 &camera,
@@ -154,7 +289,11 @@ fn main() {
         fps_counter += 1;
         if fps_timer.elapsed().as_secs_f32() >= 1.0 {
             let fps = fps_counter as f32 / fps_timer.elapsed().as_secs_f32();
-            println!("FPS: {:.1}, Render: {:.2}ms", fps, render_time.as_secs_f32() * 1000.0);
+            if accumulator.samples() > 0 {
+                println!("FPS: {:.1}, Render: {:.2}ms, Accumulated: {} spp", fps, render_time.as_secs_f32() * 1000.0, accumulator.samples());
+            } else {
+                println!("FPS: {:.1}, Render: {:.2}ms", fps, render_time.as_secs_f32() * 1000.0);
+            }
             fps_counter = 0;
             fps_timer = Instant::now();
         }
@@ -166,7 +305,6 @@ fn main() {
     }
 }
 
-#[derive(Default)]
 struct InputState {
     last_mouse_pos: Option<(f32, f32)>,
     move_speed: f32,
@@ -193,7 +331,9 @@ fn handle_input(
     rotation_y: &mut f32, 
     input_state: &mut InputState,
     manual_time: &mut bool
-) {
+) -> bool {
+    let view_before = (camera.position, camera.target, *rotation_y);
+    
     let move_speed = if window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift) {
         input_state.move_speed * 3.0
     } else {
@@ -236,7 +376,7 @@ fn handle_input(
     // Trackpad/Mouse wheel zoom: handle globally so it works on trackpads
     if let Some((_, scroll_y)) = window.get_scroll_wheel() {
         if scroll_y.abs() > 0.0 {
-            let zoom_factor = scroll_y as f32 * input_state.zoom_speed.max(0.05);
+            let zoom_factor = scroll_y * input_state.zoom_speed.max(0.05);
             camera.position = camera.position + camera.get_forward() * zoom_factor;
         }
     }
@@ -280,6 +420,9 @@ fn handle_input(
     } else {
         input_state.last_mouse_pos = None;
     }
+    
+    // Reported so progressive accumulation can start over
+    (camera.position, camera.target, *rotation_y) != view_before
 }
 
 fn render_parallel(scene: &Scene, camera: &Camera, buffer: &mut [u32], time: f32, rotation_y: f32, opts: raytracer::RenderOptions) {
@@ -294,6 +437,7 @@ fn render_parallel(scene: &Scene, camera: &Camera, buffer: &mut [u32], time: f32
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_parallel_scaled(scene: &Scene, camera: &Camera, full_buffer: &mut [u32], lowres_buffer: &mut Vec<u32>, time: f32, rotation_y: f32, scale_factor: usize, opts: raytracer::RenderOptions) {
     if scale_factor <= 1 {
         render_parallel(scene, camera, full_buffer, time, rotation_y, opts);
@@ -316,14 +460,15 @@ fn render_parallel_scaled(scene: &Scene, camera: &Camera, full_buffer: &mut [u32
         let mut src_y = y / scale_factor;
         if src_y >= lh { src_y = lh - 1; }
         let dest_row = &mut full_buffer[y * WIDTH..(y + 1) * WIDTH];
-        for x in 0..WIDTH {
+        for (x, dest) in dest_row.iter_mut().enumerate() {
             let mut src_x = x / scale_factor;
             if src_x >= lw { src_x = lw - 1; }
-            dest_row[x] = lowres_buffer[src_y * lw + src_x];
+            *dest = lowres_buffer[src_y * lw + src_x];
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_checkerboard_scaled(
     scene: &Scene,
     camera: &Camera,
@@ -352,7 +497,7 @@ fn render_checkerboard_scaled(
                 }
             }
         });
-        prev_full_buffer.copy_from_slice(&full_buffer);
+        prev_full_buffer.copy_from_slice(full_buffer);
         return;
     }
     // Low-res checkerboard
@@ -378,10 +523,10 @@ fn render_checkerboard_scaled(
         let mut src_y = y / scale_factor;
         if src_y >= lh { src_y = lh - 1; }
         let dest_row = &mut full_buffer[y * WIDTH..(y + 1) * WIDTH];
-        for x in 0..WIDTH {
+        for (x, dest) in dest_row.iter_mut().enumerate() {
             let mut src_x = x / scale_factor;
             if src_x >= lw { src_x = lw - 1; }
-            dest_row[x] = lowres_buffer[src_y * lw + src_x];
+            *dest = lowres_buffer[src_y * lw + src_x];
         }
     }
     *prev_lowres_buffer = lowres_buffer.clone();
@@ -389,24 +534,95 @@ fn render_checkerboard_scaled(
 
 // (Removed duplicate alternate version)
 
-fn create_minecraft_scene() -> Scene {
+// Vanilla blocks, retextured from `--resource-pack <dir>` when given
+fn block_registry_from_args(args: &[String]) -> BlockRegistry {
+    let mut registry = BlockRegistry::minecraft();
+    if let Some(i) = args.iter().position(|a| a == "--resource-pack") {
+        let dir = match args.get(i + 1) {
+            Some(dir) => dir,
+            None => {
+                eprintln!("--resource-pack needs a directory");
+                std::process::exit(2);
+            }
+        };
+        match ResourcePack::load(std::path::Path::new(dir)) {
+            Ok(pack) => {
+                let faces = pack.apply(&mut registry);
+                println!("Resource pack {}: {} textures, {} block faces replaced", dir, pack.len(), faces);
+            },
+            Err(e) => eprintln!("Could not load resource pack {}: {} (using procedural textures)", dir, e),
+        }
+    }
+    registry
+}
+
+fn exit_with_usage(error: &str) -> ! {
+    eprintln!("{}\n\n{}\n\n{}", error, USAGE, headless::USAGE);
+    std::process::exit(2);
+}
+
+// `--bvh median|sah` and `--bvh-leaf-size N` pick the builder
+fn bvh_config_from_args(args: &[String]) -> Result<BvhConfig, String> {
+    let value_of = |flag: &str| match args.iter().position(|a| a == flag) {
+        Some(i) => args.get(i + 1).map(|v| Some(v.as_str())).ok_or_else(|| format!("{} needs a value", flag)),
+        None => Ok(None),
+    };
+    let mut config = match value_of("--bvh")? {
+        Some("median") => BvhConfig::median(),
+        Some("sah") | None => BvhConfig::default(),
+        Some(other) => return Err(format!("unknown BVH builder {} (median, sah)", other)),
+    };
+    if let Some(size) = value_of("--bvh-leaf-size")? {
+        config.max_leaf_size = size.trim().parse().map_err(|_| format!("invalid value for --bvh-leaf-size: {}", size))?;
+        if config.max_leaf_size == 0 {
+            return Err("--bvh-leaf-size must be at least 1".to_string());
+        }
+    }
+    Ok(config)
+}
+
+// Builds the scene's BVH; with `print_stats` (`--bvh-stats`) its quality is printed next
+// to the median builder's for comparison
+fn build_scene_bvh(scene: &mut Scene, config: &BvhConfig, print_stats: bool) {
+    let build_start = Instant::now();
+    let tree = build_bvh(&scene.objects, config);
+    let build_time = build_start.elapsed();
+    
+    if print_stats {
+        let median = BvhConfig::median();
+        let median_start = Instant::now();
+        if let Some(bvh) = build_bvh(&scene.objects, &median) {
+            println!("BVH median (leaf {}): {} [{:.2}ms]", median.max_leaf_size, bvh.stats(), median_start.elapsed().as_secs_f32() * 1000.0);
+        }
+        if let Some(bvh) = &tree {
+            println!("BVH {:?} (leaf {}): {} [{:.2}ms]", config.builder, config.max_leaf_size, bvh.stats(), build_time.as_secs_f32() * 1000.0);
+        }
+    }
+    // Traversal runs on the flattened layout
+    scene.bvh = tree.as_ref().map(FlatBvh::from_tree);
+}
+
+fn create_minecraft_scene(registry: BlockRegistry) -> Scene {
     let mut scene = Scene::new();
     
-    // Create Minecraft block materials
-    let materials = create_minecraft_materials();
+    // Grid-aligned blocks live in one voxel world instead of a Cube each
+    let mut world = VoxelWorld::centered(Arc::new(registry), &mut scene.materials);
+    let blocks = MinecraftBlocks::new(&world);
     
     // Create terrain base
-    create_terrain(&mut scene, &materials);
+    create_terrain(&mut world, &blocks);
     
     // Create structures
-    create_house(&mut scene, &materials);
-    create_tower(&mut scene, &materials);
-    create_water_features(&mut scene, &materials);
-    create_nether_portal(&mut scene, &materials);
-    create_campfire(&mut scene, &materials);
+    create_house(&mut scene, &mut world, &blocks);
+    create_tower(&mut scene, &mut world, &blocks);
+    create_water_features(&mut world, &blocks);
+    create_nether_portal(&mut scene, &mut world, &blocks);
+    create_campfire(&mut scene, &blocks);
     
     // Load 3D models
-    load_3d_models(&mut scene, &materials);
+    load_3d_models(&mut scene, &mut world, &blocks);
+    
+    scene.objects.push(Box::new(world));
     
     // Add lights
     setup_lighting(&mut scene);
@@ -414,183 +630,126 @@ fn create_minecraft_scene() -> Scene {
     // Create skybox
     scene.skybox = Some(create_minecraft_skybox());
     
+    // Ground haze for light shafts; update_minecraft_scene thickens it at dawn and night
+    scene.volume = Some(VolumetricFog::height(Vec3::new(0.9, 0.9, 1.0), FOG_DAY_DENSITY, 0.0, 0.25).with_anisotropy(0.6));
+    
     scene
 }
 
-fn create_minecraft_materials() -> MinecraftMaterials {
-    MinecraftMaterials::new()
-}
-
-struct MinecraftMaterials {
-    pub grass: Material,
-    pub stone: Material,
-    pub wood: Material,
-    pub water: Material,
-    pub glass: Material,
-    pub diamond: Material,
-    pub obsidian: Material,
-    pub glowstone: Material,
-    pub portal: Material,
-    pub campfire: Material,
+// Block IDs the scene builders place, resolved once from the registry
+struct MinecraftBlocks {
+    materials: BlockMaterials,
+    pub grass: BlockId,
+    pub stone: BlockId,
+    pub planks: BlockId,
+    pub log: BlockId,
+    pub leaves: BlockId,
+    pub water: BlockId,
+    pub glass: BlockId,
+    pub diamond: BlockId,
+    pub obsidian: BlockId,
+    pub glowstone: BlockId,
+    pub portal: BlockId,
+    pub campfire: BlockId,
+    pub torch: BlockId,
 }
 
-impl MinecraftMaterials {
-    fn new() -> Self {
+impl MinecraftBlocks {
+    fn new(world: &VoxelWorld) -> Self {
+        let registry = world.registry();
         Self {
-            grass: Material::new()
-                .with_texture(Texture::minecraft_grass())
-                .with_properties(Vec3::new(0.4, 0.8, 0.2), 0.1, 0.0, 0.05),
-            
-            stone: Material::new()
-                .with_texture(Texture::minecraft_stone())
-                .with_properties(Vec3::new(0.6, 0.6, 0.6), 0.2, 0.0, 0.1),
-            
-            wood: Material::new()
-                .with_texture(Texture::minecraft_wood())
-                .with_properties(Vec3::new(0.8, 0.5, 0.3), 0.1, 0.0, 0.05),
-            
-            water: Material::new()
-                .with_texture(Texture::animated_water())
-                .with_properties(Vec3::new(0.2, 0.4, 0.8), 0.9, 0.8, 0.3)
-                .with_refraction(1.33),
-            
-            glass: Material::new()
-                .with_texture(Texture::solid_color(Vec3::new(0.9, 0.9, 1.0)))
-                .with_properties(Vec3::new(0.9, 0.9, 1.0), 0.9, 0.9, 0.1)
-                .with_refraction(1.5),
-            
-            diamond: Material::new()
-                .with_texture(Texture::minecraft_diamond())
-                .with_properties(Vec3::new(0.7, 0.9, 1.0), 0.95, 0.2, 0.8)
-                .with_refraction(2.4),
-            
-            obsidian: Material::new()
-                .with_texture(Texture::minecraft_obsidian())
-                .with_properties(Vec3::new(0.1, 0.05, 0.2), 0.3, 0.0, 0.6),
-            
-            glowstone: Material::emissive(Vec3::new(1.0, 0.8, 0.4), 2.0)
-                .with_texture(Texture::minecraft_glowstone()),
-            
-            portal: Material::new()
-                .with_texture(Texture::nether_portal())
-                .with_properties(Vec3::new(0.5, 0.1, 0.8), 0.1, 0.9, 0.3)
-                .with_emissive(Vec3::new(0.3, 0.1, 0.5)),
-            
-            campfire: Material::emissive(Vec3::new(1.0, 0.4, 0.1), 3.0)
-                .with_texture(Texture::animated_fire()),
+            grass: registry.expect_id("minecraft:grass_block"),
+            stone: registry.expect_id("minecraft:stone"),
+            planks: registry.expect_id("minecraft:oak_planks"),
+            log: registry.expect_id("minecraft:oak_log"),
+            leaves: registry.expect_id("minecraft:oak_leaves"),
+            water: registry.expect_id("minecraft:water"),
+            glass: registry.expect_id("minecraft:glass"),
+            diamond: registry.expect_id("minecraft:diamond_block"),
+            obsidian: registry.expect_id("minecraft:obsidian"),
+            glowstone: registry.expect_id("minecraft:glowstone"),
+            portal: registry.expect_id("minecraft:nether_portal"),
+            campfire: registry.expect_id("minecraft:campfire"),
+            torch: registry.expect_id("minecraft:torch"),
+            materials: world.block_materials().clone(),
         }
     }
+    
+    // Material for off-grid primitives that reuse a block's look
+    fn material(&self, id: BlockId) -> MaterialId {
+        self.materials.get(id, BlockFace::Side)
+    }
 }
 
-fn create_terrain(scene: &mut Scene, materials: &MinecraftMaterials) {
+fn create_terrain(world: &mut VoxelWorld, blocks: &MinecraftBlocks) {
     // Create layered terrain
-    for x in -15..15 {
-        for z in -15..15 {
+    for x in -TERRAIN_HALF_SIZE..TERRAIN_HALF_SIZE {
+        for z in -TERRAIN_HALF_SIZE..TERRAIN_HALF_SIZE {
             // Grass layer
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(x as f32, -1.0, z as f32),
-                1.0,
-                materials.grass.clone(),
-            )));
+            world.set_block(x, -1, z, blocks.grass);
             
             // Stone layers below
             for y in -4..-1 {
                 if (x + z + y) % 3 != 0 { // Varied stone placement
-                    scene.objects.push(Box::new(Cube::new(
-                        Vec3::new(x as f32, y as f32, z as f32),
-                        1.0,
-                        materials.stone.clone(),
-                    )));
+                    world.set_block(x, y, z, blocks.stone);
                 }
             }
         }
     }
 }
 
-fn create_house(scene: &mut Scene, materials: &MinecraftMaterials) {
+fn create_house(scene: &mut Scene, world: &mut VoxelWorld, blocks: &MinecraftBlocks) {
     // Wooden house foundation
-    for x in -2..3 {
-        for z in -2..3 {
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(x as f32, 0.0, z as f32),
-                1.0,
-                materials.wood.clone(),
-            )));
-        }
-    }
+    world.fill((-2, 0, -2), (2, 0, 2), blocks.planks);
     
     // Walls
     for y in 1..4 {
         // Front and back walls
         for x in -2..3 {
             if y == 2 && x == 0 { continue; } // Door
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(x as f32, y as f32, -2.0),
-                1.0,
-                materials.wood.clone(),
-            )));
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(x as f32, y as f32, 2.0),
-                1.0,
-                materials.wood.clone(),
-            )));
+            world.set_block(x, y, -2, blocks.planks);
+            world.set_block(x, y, 2, blocks.planks);
         }
         
         // Side walls
         for z in -1..2 {
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(-2.0, y as f32, z as f32),
-                1.0,
-                materials.wood.clone(),
-            )));
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(2.0, y as f32, z as f32),
-                1.0,
-                materials.wood.clone(),
-            )));
+            world.set_block(-2, y, z, blocks.planks);
+            world.set_block(2, y, z, blocks.planks);
         }
     }
     
     // Glass windows
-    scene.objects.push(Box::new(Cube::new(
-        Vec3::new(-2.0, 2.0, 0.0),
-        1.0,
-        materials.glass.clone(),
-    )));
-    scene.objects.push(Box::new(Cube::new(
-        Vec3::new(2.0, 2.0, 0.0),
-        1.0,
-        materials.glass.clone(),
-    )));
+    world.set_block(-2, 2, 0, blocks.glass);
+    world.set_block(2, 2, 0, blocks.glass);
     
     // Roof
-    for x in -3..4 {
-        for z in -3..4 {
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(x as f32, 4.0, z as f32),
-                1.0,
-                materials.stone.clone(),
-            )));
-        }
-    }
-    // Torches (emissive blocks + point lights) at corners
+    world.fill((-3, 4, -3), (3, 4, 3), blocks.stone);
+    // Torches at the corners; their light comes from emissive light extraction
     let torch_positions = [(-2.5, 1.5, -2.5), (2.5, 1.5, -2.5), (-2.5, 1.5, 2.5), (2.5, 1.5, 2.5)];
     for (tx, ty, tz) in torch_positions {
         scene.objects.push(Box::new(Cube::new(
-            Vec3::new(tx as f32, ty as f32, tz as f32),
+            Vec3::new(tx, ty, tz),
             0.3,
-            materials.campfire.clone(),
+            blocks.material(blocks.torch),
         )));
-        scene.lights.push(Light {
-            position: Vec3::new(tx as f32, ty as f32 + 0.2, tz as f32),
-            color: Vec3::new(1.0, 0.6, 0.2),
-            intensity: 1.8,
-            light_type: LightType::Point,
-        });
+    }
+    
+    // Flickering flames licking up from the torch tops
+    let flame = scene.add_material(Material::emissive(Vec3::new(1.0, 0.75, 0.3), 3.0));
+    for (tx, ty, tz) in torch_positions {
+        scene.add_particles(
+            ParticleEmitter::new(Vec3::new(tx, ty + 0.17, tz), flame)
+                .with_spread(Vec3::new(0.06, 0.0, 0.06))
+                .with_velocity(Vec3::new(0.0, 0.3, 0.0), 0.05)
+                .with_forces(0.0, 0.6)
+                .with_lifetime(0.4, 20.0)
+                .with_size(0.05, 0.01)
+                .with_shadows(false),
+        );
     }
 }
 
-fn create_tower(scene: &mut Scene, materials: &MinecraftMaterials) {
+fn create_tower(scene: &mut Scene, world: &mut VoxelWorld, blocks: &MinecraftBlocks) {
     let tower_x = 8.0;
     let tower_z = 8.0;
     
@@ -604,101 +763,81 @@ fn create_tower(scene: &mut Scene, materials: &MinecraftMaterials) {
             scene.objects.push(Box::new(Cube::new(
                 Vec3::new(x, y as f32, z),
                 1.0,
-                materials.stone.clone(),
+                blocks.material(blocks.stone),
             )));
         }
     }
     
     // Glowstone at the top
-    scene.objects.push(Box::new(Cube::new(
-        Vec3::new(tower_x, 12.0, tower_z),
-        1.0,
-        materials.glowstone.clone(),
-    )));
+    world.set_block(tower_x as i32, 12, tower_z as i32, blocks.glowstone);
     
     // Diamond block as decoration (spinning)
     scene.objects.push(Box::new(SpinningCube::new(
         Vec3::new(tower_x, 6.0, tower_z),
         1.0,
-        materials.diamond.clone(),
+        blocks.material(blocks.diamond),
         0.8,
     )));
 }
 
-fn create_water_features(scene: &mut Scene, materials: &MinecraftMaterials) {
-    // Water pond
+fn create_water_features(world: &mut VoxelWorld, blocks: &MinecraftBlocks) {
+    // Water pond, dug one block deeper in the middle so absorption shows the depth.
+    // Water blocks rather than cubes: rays and shadow rays cross each run as one medium.
     for x in 5..9 {
         for z in -3..1 {
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(x as f32, -0.8, z as f32),
-                1.0,
-                materials.water.clone(),
-            )));
+            let depth = if (6..8).contains(&x) && (-2..0).contains(&z) { 2 } else { 1 };
+            world.fill((x, -depth, z), (x, -1, z), blocks.water);
+            world.set_block(x, -1 - depth, z, blocks.stone);
         }
     }
     
     // Stone around water
     for x in 4..10 {
         for z in -4..2 {
-            if x >= 5 && x < 9 && z >= -3 && z < 1 { continue; }
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(x as f32, -1.0, z as f32),
-                1.0,
-                materials.stone.clone(),
-            )));
+            if (5..9).contains(&x) && (-3..1).contains(&z) { continue; }
+            world.set_block(x, -1, z, blocks.stone);
         }
     }
 }
 
-fn create_nether_portal(scene: &mut Scene, materials: &MinecraftMaterials) {
-    let portal_x = -8.0;
-    let portal_z = 0.0;
+fn create_nether_portal(scene: &mut Scene, world: &mut VoxelWorld, blocks: &MinecraftBlocks) {
+    let portal_x = -8;
+    let portal_z = 0;
     
     // Obsidian frame
     for y in 0..5 {
-        scene.objects.push(Box::new(Cube::new(
-            Vec3::new(portal_x - 1.0, y as f32, portal_z),
-            1.0,
-            materials.obsidian.clone(),
-        )));
-        scene.objects.push(Box::new(Cube::new(
-            Vec3::new(portal_x + 2.0, y as f32, portal_z),
-            1.0,
-            materials.obsidian.clone(),
-        )));
+        world.set_block(portal_x - 1, y, portal_z, blocks.obsidian);
+        world.set_block(portal_x + 2, y, portal_z, blocks.obsidian);
     }
     
     for x in 0..2 {
-        scene.objects.push(Box::new(Cube::new(
-            Vec3::new(portal_x + x as f32, -1.0, portal_z),
-            1.0,
-            materials.obsidian.clone(),
-        )));
-        scene.objects.push(Box::new(Cube::new(
-            Vec3::new(portal_x + x as f32, 4.0, portal_z),
-            1.0,
-            materials.obsidian.clone(),
-        )));
+        world.set_block(portal_x + x, -1, portal_z, blocks.obsidian);
+        world.set_block(portal_x + x, 4, portal_z, blocks.obsidian);
     }
     
     // Portal effect inside
-    for y in 0..4 {
-        for x in 0..2 {
-            scene.objects.push(Box::new(Cube::new(
-                Vec3::new(portal_x + x as f32, y as f32, portal_z),
-                1.0,
-                materials.portal.clone(),
-            )));
-        }
-    }
+    world.fill((portal_x, 0, portal_z), (portal_x + 1, 3, portal_z), blocks.portal);
+    
+    // Purple motes swirling in the portal plane and drifting out of it
+    let mote = scene.add_material(Material::emissive(Vec3::new(0.7, 0.3, 1.0), 2.5));
+    scene.add_particles(
+        ParticleEmitter::new(Vec3::new(portal_x as f32 + 0.5, 1.5, portal_z as f32), mote)
+            .with_spread(Vec3::new(0.8, 0.8, 0.1))
+            .with_velocity(Vec3::zero(), 0.35)
+            .with_swirl(1.5, Vec3::new(0.0, 0.0, 1.0))
+            .with_lifetime(2.0, 12.0)
+            .with_size(0.04, 0.0)
+            .with_shape(ParticleShape::Billboard)
+            .with_shadows(false),
+    );
 }
 
-fn create_campfire(scene: &mut Scene, materials: &MinecraftMaterials) {
+fn create_campfire(scene: &mut Scene, blocks: &MinecraftBlocks) {
     // Campfire in the center
     scene.objects.push(Box::new(Cube::new(
         Vec3::new(0.0, 0.2, 6.0),
         0.8,
-        materials.campfire.clone(),
+        blocks.material(blocks.campfire),
     )));
     
     // Stone circle around campfire
@@ -710,39 +849,58 @@ fn create_campfire(scene: &mut Scene, materials: &MinecraftMaterials) {
         scene.objects.push(Box::new(Cube::new(
             Vec3::new(x, 0.0, z),
             1.0,
-            materials.stone.clone(),
+            blocks.material(blocks.stone),
         )));
     }
-    // Fire sparks: small emissive triangles around the campfire
-    let center = Vec3::new(0.0, 0.8, 6.0);
-    for i in 0..20 {
-        let a = (i as f32) * 0.314;
-        let r = 0.2 + (i as f32 % 3.0) * 0.03;
-        let p0 = center + Vec3::new(r * a.cos(), (i as f32 % 5.0) * 0.05, r * a.sin());
-        let p1 = p0 + Vec3::new(0.03, 0.06, 0.0);
-        let p2 = p0 + Vec3::new(-0.03, 0.06, 0.0);
-        let tri = Triangle::new(p0, p1, p2, materials.campfire.clone());
-        scene.objects.push(Box::new(tri));
-    }
+    // Fire sparks shoot up and arc back down; smoke rises and spreads above them
+    let spark = scene.add_material(Material::emissive(Vec3::new(1.0, 0.55, 0.15), 4.0));
+    scene.add_particles(
+        ParticleEmitter::new(Vec3::new(0.0, 0.65, 6.0), spark)
+            .with_spread(Vec3::new(0.25, 0.0, 0.25))
+            .with_velocity(Vec3::new(0.0, 1.6, 0.0), 0.5)
+            .with_forces(1.5, 0.0)
+            .with_lifetime(1.6, 15.0)
+            .with_size(0.03, 0.01)
+            .with_shadows(false),
+    );
+    let smoke = scene.add_material(Material::new().with_properties(Vec3::new(0.45, 0.45, 0.47), 0.0, 0.0, 0.0));
+    scene.add_particles(
+        ParticleEmitter::new(Vec3::new(0.0, 0.9, 6.0), smoke)
+            .with_spread(Vec3::new(0.15, 0.0, 0.15))
+            .with_velocity(Vec3::new(0.15, 0.4, 0.05), 0.1)
+            .with_forces(0.0, 0.15)
+            .with_lifetime(4.0, 4.0)
+            .with_size(0.12, 0.5)
+            .with_shape(ParticleShape::Billboard)
+            .with_opacity(0.5),
+    );
 }
 
-fn load_3d_models(scene: &mut Scene, materials: &MinecraftMaterials) {
+fn load_3d_models(scene: &mut Scene, world: &mut VoxelWorld, blocks: &MinecraftBlocks) {
     // Ensure there is at least one OBJ in assets; if missing, write a tiny model
     ensure_obj_asset();
     // Try to load a model (fallback to procedural if file doesn't exist)
-    if let Ok(model) = ObjModel::load_from_file("assets/tree.obj", materials.wood.clone()) {
+    if let Ok(model) = ObjModel::load_from_file("assets/tree.obj", blocks.material(blocks.log)) {
         for triangle in model.triangles {
             scene.objects.push(triangle);
         }
     } else {
         // Create a procedural tree
-        create_procedural_tree(scene, materials);
+        create_procedural_tree(world, blocks);
     }
-    // Ensure bench asset exists and load it
+    // Ensure bench asset exists and load it once; instances seat it around the campfire
     ensure_bench_asset();
-    if let Ok(model) = ObjModel::load_from_file("assets/bench.obj", materials.wood.clone()) {
-        for triangle in model.triangles {
-            scene.objects.push(triangle);
+    if let Ok(model) = ObjModel::load_from_file("assets/bench.obj", blocks.material(blocks.log)) {
+        let bench = Arc::new(model.into_mesh());
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let placements = [
+            (Vec3::new(0.0, 0.0, 9.5), 0.0),
+            (Vec3::new(-3.5, 0.0, 6.0), std::f32::consts::FRAC_PI_2),
+            (Vec3::new(3.5, 0.0, 6.0), -std::f32::consts::FRAC_PI_2),
+        ];
+        for (position, angle) in placements {
+            let transform = Mat4::from_trs(position, Quat::from_axis_angle(up, angle), Vec3::one());
+            scene.objects.push(Box::new(Instance::new(bench.clone(), transform)));
         }
     }
 }
@@ -804,29 +962,21 @@ f 7 2 8\n";
     }
 }
 
-fn create_procedural_tree(scene: &mut Scene, materials: &MinecraftMaterials) {
-    let tree_x = -5.0;
-    let tree_z = -8.0;
+fn create_procedural_tree(world: &mut VoxelWorld, blocks: &MinecraftBlocks) {
+    let tree_x = -5;
+    let tree_z = -8;
     
     // Tree trunk
     for y in 0..6 {
-        scene.objects.push(Box::new(Cube::new(
-            Vec3::new(tree_x, y as f32, tree_z),
-            1.0,
-            materials.wood.clone(),
-        )));
+        world.set_block(tree_x, y, tree_z, blocks.log);
     }
     
     // Tree leaves
     for x in -2i32..3i32 {
         for z in -2i32..3i32 {
             for y in 5..8 {
-                if (x.abs() + z.abs()) <= 2 {
-                    scene.objects.push(Box::new(Cube::new(
-                        Vec3::new(tree_x + x as f32, y as f32, tree_z + z as f32),
-                        1.0,
-                        materials.grass.clone(), // Using grass texture for leaves
-                    )));
+                if (x.abs() + z.abs()) <= 2 && world.get_block(tree_x + x, y, tree_z + z) == AIR {
+                    world.set_block(tree_x + x, y, tree_z + z, blocks.leaves);
                 }
             }
         }
@@ -834,79 +984,96 @@ fn create_procedural_tree(scene: &mut Scene, materials: &MinecraftMaterials) {
 }
 
 fn setup_lighting(scene: &mut Scene) {
-    // Sun (main directional light)
-    scene.lights.push(Light {
-        position: Vec3::new(50.0, 50.0, 30.0),
-        color: Vec3::new(1.0, 1.0, 0.9),
-        intensity: 1.5,
-        light_type: LightType::Directional(Vec3::new(-1.0, -1.0, -0.5).normalize()),
-    });
+    // Sun (main light; a disc in the sky so shadows get soft edges)
+    scene.lights.push(Light::sun(Vec3::new(-1.0, -1.0, -0.5), SUN_ANGULAR_DIAMETER, Vec3::new(1.0, 1.0, 0.9), 1.5));
+    // Moon, opposite the sun; takes over at night
+    scene.lights.push(Light::sun(Vec3::new(1.0, 1.0, 0.5), SUN_ANGULAR_DIAMETER, Vec3::new(0.75, 0.82, 1.0), 0.0));
+    // Lightning; dark until a thunderstorm flashes
+    scene.lights.push(Light::directional(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.8, 0.85, 1.0), 0.0));
     
-    // Campfire light
-    scene.lights.push(Light {
-        position: Vec3::new(0.0, 1.5, 6.0),
-        color: Vec3::new(1.0, 0.5, 0.2),
-        intensity: 3.0,
-        light_type: LightType::Point,
-    });
-    
-    // Glowstone light
-    scene.lights.push(Light {
-        position: Vec3::new(8.0, 13.0, 8.0),
-        color: Vec3::new(1.0, 0.8, 0.4),
-        intensity: 2.5,
-        light_type: LightType::Point,
-    });
-    
-    // Portal light
-    scene.lights.push(Light {
-        position: Vec3::new(-7.0, 2.0, 0.0),
-        color: Vec3::new(0.5, 0.1, 0.8),
-        intensity: 2.0,
-        light_type: LightType::Point,
-    });
+    // Torches, campfire, glowstone and portal light the scene as sampled emitters
+    scene.extract_emissive_lights();
 }
 
 fn create_minecraft_skybox() -> Skybox {
-    Skybox::textured(
-        Vec3::new(0.5, 0.8, 1.0), // Day sky blue
-        Vec3::new(1.0, 0.6, 0.3), // Sunset orange
+    Skybox::physical(
+        SKY_TURBIDITY,
+        SUN_ANGULAR_DIAMETER,
         Vec3::new(0.1, 0.1, 0.3), // Night dark blue
         Vec3::new(0.3, 0.2, 0.4)  // Night horizon
     )
+    .with_clouds(CloudLayer::new(CloudStyle::Soft))
 }
 
-fn update_minecraft_scene(scene: &mut Scene, time: f32, speed: f32) {
+fn update_minecraft_scene(scene: &mut Scene, time: f32, speed: f32, weather: Weather) {
     // Day/night cycle
     let day_progress = (time * speed).sin() * 0.5 + 0.5; // Slower cycle adjustable
+    let conditions = weather.conditions(time);
+    scene.weather = conditions;
+    let sun_dimming = 1.0 - OVERCAST_SUN_DIMMING * conditions.overcast;
+    scene.update_particles(time);
+    scene.pose_objects(time);
+    
+    // Update skybox first: the sun light takes its colour from the sky model
+    if let Some(skybox) = &mut scene.skybox {
+        skybox.update_time_of_day_with_speed(time, speed);
+        skybox.set_weather(conditions.overcast, conditions.lightning);
+    }
+    let sun_light = scene.skybox.as_ref().and_then(Skybox::sun_light);
+    let moon_light = scene.skybox.as_ref().map(Skybox::moon_light);
     
     // Update sun
     if let Some(main_light) = scene.lights.get_mut(0) {
         let sun_angle = time * speed;
-        // Update directional light to follow the sun path
+        // Update directional light to follow the sun path; it shines from the sky disc down
         let sun_dir = Vec3::new(0.3, sun_angle.sin(), sun_angle.cos()).normalize();
-        main_light.light_type = LightType::Directional(sun_dir);
+        main_light.set_direction(-sun_dir);
         
-        if day_progress > 0.3 {
-            // Day
-            main_light.color = Vec3::new(1.0, 1.0, 0.9);
-            main_light.intensity = day_progress * 2.0;
-        } else {
-            // Night/Dawn/Dusk
-            main_light.color = Vec3::new(1.0, 0.6, 0.3);
-            main_light.intensity = (day_progress + 0.2) * 0.8;
+        // Atmosphere-filtered colour, reddening at sunset and gone once the sun has set
+        if let Some((color, strength)) = sun_light {
+            main_light.color = color;
+            main_light.intensity = SUN_INTENSITY * strength * sun_dimming;
         }
     }
     
-    // Update skybox
-    if let Some(skybox) = &mut scene.skybox {
-        skybox.update_time_of_day_with_speed(time, speed);
+    // Moon
+    if let Some(moon) = scene.lights.get_mut(1) {
+        let sun_angle = time * speed;
+        let sun_dir = Vec3::new(0.3, sun_angle.sin(), sun_angle.cos()).normalize();
+        moon.set_direction(sun_dir);
+        if let Some((color, strength)) = moon_light {
+            moon.color = color;
+            moon.intensity = MOON_INTENSITY * strength * sun_dimming;
+        }
     }
+    
+    if let Some(lightning) = scene.lights.get_mut(2) {
+        if conditions.lightning > 0.0 {
+            lightning.set_direction(conditions.lightning_direction);
+        }
+        lightning.intensity = LIGHTNING_INTENSITY * conditions.lightning;
+    }
+    
+    // Rain and snow thicken the haze towards the colour of the sky at the horizon
+    let precipitation = conditions.rain.max(conditions.snowfall);
+    if let Some(volume) = &mut scene.volume {
+        let clear_density = FOG_NIGHT_DENSITY + (FOG_DAY_DENSITY - FOG_NIGHT_DENSITY) * day_progress;
+        volume.density = clear_density + (WEATHER_HAZE_DENSITY - clear_density).max(0.0) * precipitation;
+    }
+    scene.fog = if precipitation > 0.0 {
+        let horizon = scene.skybox.as_ref().map_or(Vec3::new(0.5, 0.5, 0.55), |skybox| skybox.color_at(Vec3::new(1.0, 0.05, 0.0)));
+        Some(Fog::exponential(horizon, WEATHER_HAZE_DENSITY * precipitation))
+    } else {
+        None
+    };
 
     // Modulate water brightness with day_progress: find water materials and tweak albedo subtly
     // (simple pass – in a larger engine we'd separate instances; here we scale ambient for effect)
     let water_ambient = Vec3::new(0.05, 0.07, 0.1).lerp(Vec3::new(0.12, 0.18, 0.25), day_progress);
-    scene.ambient_light = Vec3::new(0.08, 0.08, 0.12) + water_ambient * 0.2;
+    let clear_ambient = Vec3::new(0.08, 0.08, 0.12) + water_ambient * 0.2;
+    // Overcast light is flat and grey; flashes light everything at once
+    let grey = (clear_ambient.x + clear_ambient.y + clear_ambient.z) / 3.0;
+    scene.ambient_light = clear_ambient.lerp(Vec3::new(grey, grey, grey), conditions.overcast) + Vec3::new(0.8, 0.85, 1.0) * (0.2 * conditions.lightning);
 }
 
 fn color_to_u32(color: Vec3) -> u32 {
@@ -916,4 +1083,30 @@ fn color_to_u32(color: Vec3) -> u32 {
     let g = (color.y.powf(gamma).clamp(0.0, 1.0) * 255.0) as u32;
     let b = (color.z.powf(gamma).clamp(0.0, 1.0) * 255.0) as u32;
     (r << 16) | (g << 8) | b
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn bvh_config(line: &str) -> Result<BvhConfig, String> {
+        let args: Vec<String> = std::iter::once("minecraft").chain(line.split_whitespace()).map(String::from).collect();
+        bvh_config_from_args(&args)
+    }
+    
+    #[test]
+    fn bvh_flags_pick_the_builder_and_leaf_size() {
+        let config = bvh_config("--headless --bvh median --bvh-leaf-size 2").unwrap();
+        assert_eq!(config.builder, BvhBuilder::Median);
+        assert_eq!(config.max_leaf_size, 2);
+        assert_eq!(bvh_config("").unwrap().builder, BvhBuilder::Sah);
+    }
+    
+    #[test]
+    fn bad_bvh_flags_are_rejected() {
+        assert_eq!(bvh_config("--bvh octree").unwrap_err(), "unknown BVH builder octree (median, sah)");
+        assert_eq!(bvh_config("--bvh").unwrap_err(), "--bvh needs a value");
+        assert_eq!(bvh_config("--bvh-leaf-size four").unwrap_err(), "invalid value for --bvh-leaf-size: four");
+        assert_eq!(bvh_config("--bvh-leaf-size 0").unwrap_err(), "--bvh-leaf-size must be at least 1");
+    }
+}
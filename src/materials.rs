@@ -1,6 +1,9 @@
 use crate::math::Vec3;
 use crate::texture::Texture;
 
+// Index into a MaterialTable; primitives and hits carry this instead of a Material
+pub type MaterialId = u32;
+
 #[derive(Clone)]
 pub struct Material {
     pub albedo: Vec3,
@@ -13,6 +16,8 @@ pub struct Material {
     pub roughness: f32,
     pub metallic: f32,
     pub subsurface: f32,
+    // Beer–Lambert coefficient per unit distance inside the medium (zero: clear)
+    pub absorption: Vec3,
 }
 
 impl Material {
@@ -28,6 +33,7 @@ impl Material {
             roughness: 0.5,
             metallic: 0.0,
             subsurface: 0.0,
+            absorption: Vec3::zero(),
         }
     }
     
@@ -43,6 +49,7 @@ impl Material {
             roughness: 1.0,
             metallic: 0.0,
             subsurface: 0.0,
+            absorption: Vec3::zero(),
         }
     }
     
@@ -58,6 +65,7 @@ impl Material {
             roughness,
             metallic: 1.0,
             subsurface: 0.0,
+            absorption: Vec3::zero(),
         }
     }
     
@@ -73,6 +81,7 @@ impl Material {
             roughness: 0.0,
             metallic: 0.0,
             subsurface: 0.0,
+            absorption: Vec3::zero(),
         }
     }
     
@@ -88,6 +97,7 @@ impl Material {
             roughness: 1.0,
             metallic: 0.0,
             subsurface: 0.0,
+            absorption: Vec3::zero(),
         }
     }
     
@@ -103,6 +113,7 @@ impl Material {
             roughness: 0.0,
             metallic: 0.0,
             subsurface: 0.0,
+            absorption: Material::tint_absorption(tint),
         }
     }
     
@@ -118,6 +129,7 @@ impl Material {
             roughness: 0.1,
             metallic: 0.0,
             subsurface: 0.3,
+            absorption: Vec3::new(0.9, 0.35, 0.12),
         }
     }
     
@@ -133,6 +145,7 @@ impl Material {
             roughness: 0.3,
             metallic: 0.0,
             subsurface: 0.8,
+            absorption: Vec3::new(0.7, 2.0, 0.1),
         }
     }
     
@@ -170,6 +183,31 @@ impl Material {
         self
     }
     
+    pub fn with_absorption(mut self, absorption: Vec3) -> Self {
+        self.absorption = absorption;
+        self
+    }
+    
+    // Absorption that leaves `tint` after one block of thickness, so thicker panes
+    // and longer paths come out more saturated
+    pub fn tint_absorption(tint: Vec3) -> Vec3 {
+        let coefficient = |c: f32| -c.clamp(0.001, 1.0).ln();
+        Vec3::new(coefficient(tint.x), coefficient(tint.y), coefficient(tint.z))
+    }
+    
+    // Fraction of light left after travelling `distance` through this material
+    pub fn transmittance(&self, distance: f32) -> Vec3 {
+        Vec3::new(
+            (-self.absorption.x * distance).exp(),
+            (-self.absorption.y * distance).exp(),
+            (-self.absorption.z * distance).exp(),
+        )
+    }
+    
+    pub fn is_absorbing(&self) -> bool {
+        self.absorption.length_squared() > 0.0
+    }
+    
     pub fn sample_texture(&self, uv: (f32, f32), time: f32) -> Vec3 {
         if let Some(ref texture) = self.texture {
             texture.sample(uv.0, uv.1, time)
@@ -198,6 +236,18 @@ impl Material {
         }
     }
     
+    // Normal-incidence reflectance for the metallic workflow: metals tint it with their
+    // albedo, dielectrics get 8% of `specular` (0.5 -> the common 0.04)
+    pub fn specular_f0(&self, albedo: Vec3) -> Vec3 {
+        let dielectric = 0.08 * self.specular;
+        Vec3::new(dielectric, dielectric, dielectric).lerp(albedo, self.metallic.clamp(0.0, 1.0))
+    }
+    
+    // GGX alpha; clamped so a roughness of 0 stays numerically stable
+    pub fn alpha(&self) -> f32 {
+        (self.roughness * self.roughness).max(0.002)
+    }
+    
     pub fn is_emissive(&self) -> bool {
         self.emissive.length_squared() > 0.001
     }
@@ -299,5 +349,27 @@ pub fn blend_materials(mat1: &Material, mat2: &Material, factor: f32) -> Materia
         roughness: mat1.roughness * (1.0 - t) + mat2.roughness * t,
         metallic: mat1.metallic * (1.0 - t) + mat2.metallic * t,
         subsurface: mat1.subsurface * (1.0 - t) + mat2.subsurface * t,
+        absorption: mat1.absorption.lerp(mat2.absorption, t),
+    }
+}
+
+// Scene-wide material storage, so hits can refer to materials without cloning them
+#[derive(Clone, Default)]
+pub struct MaterialTable {
+    materials: Vec<Material>,
+}
+
+impl MaterialTable {
+    pub fn new() -> Self {
+        Self { materials: Vec::new() }
+    }
+    
+    pub fn add(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        (self.materials.len() - 1) as MaterialId
     }
-}
\ No newline at end of file
+    
+    pub fn get(&self, id: MaterialId) -> &Material {
+        &self.materials[id as usize]
+    }
+}
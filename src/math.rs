@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -222,6 +222,177 @@ impl Ray {
     }
 }
 
+// Unit quaternion for rotations (w + xi + yj + zk)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quat {
+    pub fn identity() -> Self {
+        Quat { w: 1.0, x: 0.0, y: 0.0, z: 0.0 }
+    }
+    
+    // Right-handed rotation of `angle` radians around `axis`
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Quat { w: cos, x: axis.x * sin, y: axis.y * sin, z: axis.z * sin }
+    }
+    
+    // Euler angles in radians, applied around z, then x, then y
+    pub fn from_euler(angles: Vec3) -> Self {
+        Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), angles.y)
+            * Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), angles.x)
+            * Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), angles.z)
+    }
+    
+    pub fn normalize(self) -> Quat {
+        let len = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        if len > 0.0 {
+            Quat { w: self.w / len, x: self.x / len, y: self.y / len, z: self.z / len }
+        } else {
+            Quat::identity()
+        }
+    }
+    
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        // v + 2w(q x v) + 2 q x (q x v), with q the vector part
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = q.cross(v) * 2.0;
+        v + t * self.w + q.cross(t)
+    }
+}
+
+impl std::ops::Mul for Quat {
+    type Output = Quat;
+    // Hamilton product: `a * b` rotates by b, then by a
+    fn mul(self, o: Quat) -> Quat {
+        Quat {
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        }
+    }
+}
+
+// Row-major 4x4 matrix acting on column vectors (p' = M p), used for affine transforms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Mat4 { m }
+    }
+    
+    pub fn translation(t: Vec3) -> Self {
+        let mut result = Mat4::identity();
+        result.m[0][3] = t.x;
+        result.m[1][3] = t.y;
+        result.m[2][3] = t.z;
+        result
+    }
+    
+    pub fn scale(s: Vec3) -> Self {
+        let mut result = Mat4::identity();
+        result.m[0][0] = s.x;
+        result.m[1][1] = s.y;
+        result.m[2][2] = s.z;
+        result
+    }
+    
+    pub fn rotation(q: Quat) -> Self {
+        let Quat { w, x, y, z } = q.normalize();
+        let mut result = Mat4::identity();
+        result.m[0] = [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0];
+        result.m[1] = [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0];
+        result.m[2] = [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0];
+        result
+    }
+    
+    // Scale first, then rotate, then translate
+    pub fn from_trs(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        Mat4::translation(translation) * Mat4::rotation(rotation) * Mat4::scale(scale)
+    }
+    
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+    
+    // Directions ignore the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+    
+    // General inverse by cofactor expansion; None if the matrix is singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = [0.0f32; 16];
+        for (i, value) in a.iter_mut().enumerate() {
+            *value = self.m[i / 4][i % 4];
+        }
+        let mut inv = [0.0f32; 16];
+        inv[0] = a[5] * a[10] * a[15] - a[5] * a[11] * a[14] - a[9] * a[6] * a[15] + a[9] * a[7] * a[14] + a[13] * a[6] * a[11] - a[13] * a[7] * a[10];
+        inv[4] = -a[4] * a[10] * a[15] + a[4] * a[11] * a[14] + a[8] * a[6] * a[15] - a[8] * a[7] * a[14] - a[12] * a[6] * a[11] + a[12] * a[7] * a[10];
+        inv[8] = a[4] * a[9] * a[15] - a[4] * a[11] * a[13] - a[8] * a[5] * a[15] + a[8] * a[7] * a[13] + a[12] * a[5] * a[11] - a[12] * a[7] * a[9];
+        inv[12] = -a[4] * a[9] * a[14] + a[4] * a[10] * a[13] + a[8] * a[5] * a[14] - a[8] * a[6] * a[13] - a[12] * a[5] * a[10] + a[12] * a[6] * a[9];
+        inv[1] = -a[1] * a[10] * a[15] + a[1] * a[11] * a[14] + a[9] * a[2] * a[15] - a[9] * a[3] * a[14] - a[13] * a[2] * a[11] + a[13] * a[3] * a[10];
+        inv[5] = a[0] * a[10] * a[15] - a[0] * a[11] * a[14] - a[8] * a[2] * a[15] + a[8] * a[3] * a[14] + a[12] * a[2] * a[11] - a[12] * a[3] * a[10];
+        inv[9] = -a[0] * a[9] * a[15] + a[0] * a[11] * a[13] + a[8] * a[1] * a[15] - a[8] * a[3] * a[13] - a[12] * a[1] * a[11] + a[12] * a[3] * a[9];
+        inv[13] = a[0] * a[9] * a[14] - a[0] * a[10] * a[13] - a[8] * a[1] * a[14] + a[8] * a[2] * a[13] + a[12] * a[1] * a[10] - a[12] * a[2] * a[9];
+        inv[2] = a[1] * a[6] * a[15] - a[1] * a[7] * a[14] - a[5] * a[2] * a[15] + a[5] * a[3] * a[14] + a[13] * a[2] * a[7] - a[13] * a[3] * a[6];
+        inv[6] = -a[0] * a[6] * a[15] + a[0] * a[7] * a[14] + a[4] * a[2] * a[15] - a[4] * a[3] * a[14] - a[12] * a[2] * a[7] + a[12] * a[3] * a[6];
+        inv[10] = a[0] * a[5] * a[15] - a[0] * a[7] * a[13] - a[4] * a[1] * a[15] + a[4] * a[3] * a[13] + a[12] * a[1] * a[7] - a[12] * a[3] * a[5];
+        inv[14] = -a[0] * a[5] * a[14] + a[0] * a[6] * a[13] + a[4] * a[1] * a[14] - a[4] * a[2] * a[13] - a[12] * a[1] * a[6] + a[12] * a[2] * a[5];
+        inv[3] = -a[1] * a[6] * a[11] + a[1] * a[7] * a[10] + a[5] * a[2] * a[11] - a[5] * a[3] * a[10] - a[9] * a[2] * a[7] + a[9] * a[3] * a[6];
+        inv[7] = a[0] * a[6] * a[11] - a[0] * a[7] * a[10] - a[4] * a[2] * a[11] + a[4] * a[3] * a[10] + a[8] * a[2] * a[7] - a[8] * a[3] * a[6];
+        inv[11] = -a[0] * a[5] * a[11] + a[0] * a[7] * a[9] + a[4] * a[1] * a[11] - a[4] * a[3] * a[9] - a[8] * a[1] * a[7] + a[8] * a[3] * a[5];
+        inv[15] = a[0] * a[5] * a[10] - a[0] * a[6] * a[9] - a[4] * a[1] * a[10] + a[4] * a[2] * a[9] + a[8] * a[1] * a[6] - a[8] * a[2] * a[5];
+        
+        let det = a[0] * inv[0] + a[1] * inv[4] + a[2] * inv[8] + a[3] * inv[12];
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let mut m = [[0.0; 4]; 4];
+        for (i, value) in inv.iter().enumerate() {
+            m[i / 4][i % 4] = value / det;
+        }
+        Some(Mat4 { m })
+    }
+}
+
+impl std::ops::Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
+
 // Fresnel reflectance calculation
 pub fn fresnel(cos_theta: f32, eta: f32) -> f32 {
     let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
@@ -245,7 +416,86 @@ pub fn schlick(cos_theta: f32, eta: f32) -> f32 {
     r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5)
 }
 
+// Real roots of a*x^4 + b*x^3 + c*x^2 + d*x + e = 0 (Ferrari's method, in f64 and
+// polished with Newton steps), sorted ascending. Returns the roots and how many are valid.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> ([f64; 4], usize) {
+    let mut roots = [0.0; 4];
+    let mut count = 0;
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+    
+    // Depressed quartic y^4 + p*y^2 + q*y + r with x = y - b/4
+    let b2 = b * b;
+    let p = c - 0.375 * b2;
+    let q = d - 0.5 * b * c + 0.125 * b2 * b;
+    let r = e - 0.25 * b * d + 0.0625 * b2 * c - 3.0 / 256.0 * b2 * b2;
+    let mut push_quadratic = |linear: f64, constant: f64| {
+        let discriminant = linear * linear - 4.0 * constant;
+        if discriminant >= 0.0 {
+            let sqrt_d = discriminant.sqrt();
+            roots[count] = (-linear - sqrt_d) * 0.5;
+            roots[count + 1] = (-linear + sqrt_d) * 0.5;
+            count += 2;
+        }
+    };
+    
+    if q.abs() < 1e-12 {
+        // Biquadratic: solve for z = y^2
+        let discriminant = p * p - 4.0 * r;
+        if discriminant >= 0.0 {
+            for z in [(-p - discriminant.sqrt()) * 0.5, (-p + discriminant.sqrt()) * 0.5] {
+                if z >= 0.0 {
+                    push_quadratic(0.0, -z);
+                }
+            }
+        }
+    } else {
+        // A positive root m of the resolvent cubic splits the quartic into two quadratics
+        let m = largest_cubic_root(p, 0.25 * p * p - r, -0.125 * q * q);
+        if m <= 0.0 {
+            return (roots, 0);
+        }
+        let s = (2.0 * m).sqrt();
+        push_quadratic(-s, 0.5 * p + m + q / (2.0 * s));
+        push_quadratic(s, 0.5 * p + m - q / (2.0 * s));
+    }
+    
+    for root in roots.iter_mut().take(count) {
+        let mut x = *root - 0.25 * b;
+        for _ in 0..2 {
+            let f = (((x + b) * x + c) * x + d) * x + e;
+            let df = ((4.0 * x + 3.0 * b) * x + 2.0 * c) * x + d;
+            if df.abs() > 1e-12 {
+                x -= f / df;
+            }
+        }
+        *root = x;
+    }
+    roots[..count].sort_by(f64::total_cmp);
+    (roots, count)
+}
+
+// Largest real root of x^3 + a*x^2 + b*x + c = 0
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let q3 = q * q * q;
+    let x = if r * r < q3 {
+        // Three real roots; this branch of the trigonometric form is the largest
+        let theta = (r / q3.sqrt()).clamp(-1.0, 1.0).acos();
+        -2.0 * q.sqrt() * ((theta + 2.0 * std::f64::consts::PI) / 3.0).cos() - a / 3.0
+    } else {
+        let big_a = -r.signum() * (r.abs() + (r * r - q3).sqrt()).cbrt();
+        let big_b = if big_a != 0.0 { q / big_a } else { 0.0 };
+        big_a + big_b - a / 3.0
+    };
+    // One Newton step cleans up cancellation in the closed forms
+    let f = ((x + a) * x + b) * x + c;
+    let df = (3.0 * x + 2.0 * a) * x + b;
+    if df.abs() > 1e-12 { x - f / df } else { x }
+}
+
 // Noise functions for procedural textures
+#[allow(clippy::excessive_precision)] // The classic hash constant, kept as written
 pub fn hash(p: Vec3) -> f32 {
     let p3 = (p * 0.1031).fract();
     let p3_x = p3.x + p3.y * 19.19 + p3.z * 213.0;
@@ -254,7 +504,7 @@ pub fn hash(p: Vec3) -> f32 {
 
 pub fn noise(p: Vec3) -> f32 {
     let i = p.floor();
-    let f = p.fract();
+    let f = p - i; // Not fract(), which is negative below zero
     
     // Smooth interpolation
     let u = f * f * (Vec3::new(3.0, 3.0, 3.0) - f * 2.0);
@@ -321,4 +571,37 @@ pub fn tone_map_aces(color: Vec3) -> Vec3 {
     let numerator = color.component_mul(&(color * a + Vec3::new(b, b, b)));
     let denominator = color.component_mul(&(color * c + Vec3::new(d, d, d))) + Vec3::new(e, e, e);
     numerator.component_div(&denominator)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "expected {expected}, got {actual}");
+    }
+    
+    #[test]
+    fn matrix_inverse_undoes_transform() {
+        let m = Mat4::from_trs(Vec3::new(1.0, -2.0, 3.0), Quat::from_euler(Vec3::new(0.3, 1.1, -0.7)), Vec3::new(2.0, 0.5, 1.5));
+        let product = m * m.inverse().unwrap();
+        for (i, row) in product.m.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                assert_close(*value, if i == j { 1.0 } else { 0.0 }, 1e-5);
+            }
+        }
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+    
+    #[test]
+    fn quaternion_matches_rotation_matrix() {
+        let q = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), std::f32::consts::FRAC_PI_2);
+        let v = q.rotate(Vec3::new(1.0, 0.0, 0.0));
+        assert_close(v.z, -1.0, 1e-6);
+        let w = Mat4::rotation(q).transform_vector(Vec3::new(0.2, 0.4, 0.9));
+        let expected = q.rotate(Vec3::new(0.2, 0.4, 0.9));
+        assert_close(w.x, expected.x, 1e-6);
+        assert_close(w.y, expected.y, 1e-6);
+        assert_close(w.z, expected.z, 1e-6);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::math::Vec3;
-use crate::materials::Material;
-use crate::primitives::{Triangle, Primitive};
+use crate::materials::{Material, MaterialId, MaterialTable};
+use crate::primitives::{Mesh, Triangle, Primitive};
 use std::fs;
 use std::io::{BufRead, BufReader};
 
@@ -11,7 +11,7 @@ pub struct ObjModel {
 }
 
 impl ObjModel {
-    pub fn load_from_file(path: &str, material: Material) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn load_from_file(path: &str, material: MaterialId) -> Result<Self, Box<dyn std::error::Error>> {
         let file = fs::File::open(path)?;
         let reader = BufReader::new(file);
         
@@ -32,92 +32,83 @@ impl ObjModel {
             }
             
             match parts[0] {
-                "v" => {
+                "v" if parts.len() >= 4 => {
                     // Vertex position
-                    if parts.len() >= 4 {
-                        let x: f32 = parts[1].parse()?;
-                        let y: f32 = parts[2].parse()?;
-                        let z: f32 = parts[3].parse()?;
-                        let vertex = Vec3::new(x, y, z);
-                        
-                        // Update bounds
-                        bounds_min = bounds_min.min(vertex);
-                        bounds_max = bounds_max.max(vertex);
-                        
-                        vertices.push(vertex);
-                    }
+                    let x: f32 = parts[1].parse()?;
+                    let y: f32 = parts[2].parse()?;
+                    let z: f32 = parts[3].parse()?;
+                    let vertex = Vec3::new(x, y, z);
+                    
+                    // Update bounds
+                    bounds_min = bounds_min.min(vertex);
+                    bounds_max = bounds_max.max(vertex);
+                    
+                    vertices.push(vertex);
                 },
-                "vn" => {
+                "vn" if parts.len() >= 4 => {
                     // Vertex normal
-                    if parts.len() >= 4 {
-                        let x: f32 = parts[1].parse()?;
-                        let y: f32 = parts[2].parse()?;
-                        let z: f32 = parts[3].parse()?;
-                        normals.push(Vec3::new(x, y, z).normalize());
-                    }
+                    let x: f32 = parts[1].parse()?;
+                    let y: f32 = parts[2].parse()?;
+                    let z: f32 = parts[3].parse()?;
+                    normals.push(Vec3::new(x, y, z).normalize());
                 },
-                "vt" => {
+                "vt" if parts.len() >= 3 => {
                     // Texture coordinate
-                    if parts.len() >= 3 {
-                        let u: f32 = parts[1].parse()?;
-                        let v: f32 = parts[2].parse()?;
-                        uvs.push((u, v));
-                    }
+                    let u: f32 = parts[1].parse()?;
+                    let v: f32 = parts[2].parse()?;
+                    uvs.push((u, v));
                 },
-                "f" => {
+                "f" if parts.len() >= 4 => {
                     // Face
-                    if parts.len() >= 4 {
-                        let mut face_data = Vec::new();
+                    let mut face_data = Vec::new();
+                    
+                    for vertex_data in &parts[1..] {
+                        let indices: Vec<&str> = vertex_data.split('/').collect();
                         
-                        for i in 1..parts.len() {
-                            let vertex_data = parts[i];
-                            let indices: Vec<&str> = vertex_data.split('/').collect();
-                            
-                            // Parse vertex index (required)
-                            let vertex_index: usize = indices[0].parse::<usize>()?.saturating_sub(1);
+                        // Parse vertex index (required)
+                        let vertex_index: usize = indices[0].parse::<usize>()?.saturating_sub(1);
+                        
+                        // Parse texture coordinate index (optional)
+                        let uv_index = if indices.len() > 1 && !indices[1].is_empty() {
+                            Some(indices[1].parse::<usize>()?.saturating_sub(1))
+                        } else {
+                            None
+                        };
+                        
+                        // Parse normal index (optional)
+                        let normal_index = if indices.len() > 2 && !indices[2].is_empty() {
+                            Some(indices[2].parse::<usize>()?.saturating_sub(1))
+                        } else {
+                            None
+                        };
+                        
+                        if vertex_index < vertices.len() {
+                            let vertex = vertices[vertex_index];
+                            let uv = uv_index.and_then(|i| uvs.get(i)).copied().unwrap_or((0.0, 0.0));
+                            let normal = normal_index.and_then(|i| normals.get(i)).copied();
                             
-                            // Parse texture coordinate index (optional)
-                            let uv_index = if indices.len() > 1 && !indices[1].is_empty() {
-                                Some(indices[1].parse::<usize>()?.saturating_sub(1))
-                            } else {
-                                None
-                            };
+                            face_data.push((vertex, uv, normal));
+                        }
+                    }
+                    
+                    // Triangulate the face (fan triangulation for n-gons)
+                    if face_data.len() >= 3 {
+                        for i in 1..face_data.len() - 1 {
+                            let (v0, uv0, n0) = face_data[0];
+                            let (v1, uv1, n1) = face_data[i];
+                            let (v2, uv2, n2) = face_data[i + 1];
                             
-                            // Parse normal index (optional)
-                            let normal_index = if indices.len() > 2 && !indices[2].is_empty() {
-                                Some(indices[2].parse::<usize>()?.saturating_sub(1))
+                            // Calculate face normal if not provided
+                            let _face_normal = if let (Some(n0), Some(n1), Some(n2)) = (n0, n1, n2) {
+                                // Use average of vertex normals for smooth shading
+                                ((n0 + n1 + n2) / 3.0).normalize()
                             } else {
-                                None
+                                // Calculate geometric normal
+                                (v1 - v0).cross(v2 - v0).normalize()
                             };
                             
-                            if vertex_index < vertices.len() {
-                                let vertex = vertices[vertex_index];
-                                let uv = uv_index.and_then(|i| uvs.get(i)).copied().unwrap_or((0.0, 0.0));
-                                let normal = normal_index.and_then(|i| normals.get(i)).copied();
-                                
-                                face_data.push((vertex, uv, normal));
-                            }
-                        }
-                        
-                        // Triangulate the face (fan triangulation for n-gons)
-                        if face_data.len() >= 3 {
-                            for i in 1..face_data.len() - 1 {
-                                let (v0, uv0, n0) = face_data[0];
-                                let (v1, uv1, n1) = face_data[i];
-                                let (v2, uv2, n2) = face_data[i + 1];
-                                
-                                // Calculate face normal if not provided
-                                let _face_normal = if let (Some(n0), Some(n1), Some(n2)) = (n0, n1, n2) {
-                                    // Use average of vertex normals for smooth shading
-                                    ((n0 + n1 + n2) / 3.0).normalize()
-                                } else {
-                                    // Calculate geometric normal
-                                    (v1 - v0).cross(v2 - v0).normalize()
-                                };
-                                
-                                let triangle = Triangle::new_with_uvs(v0, v1, v2, uv0, uv1, uv2, material.clone());
-                                triangles.push(Box::new(triangle));
-                            }
+                            let triangle = Triangle::new_with_uvs(v0, v1, v2, uv0, uv1, uv2, material);
+                            triangles.push(Box::new(triangle));
                         }
                     }
                 },
@@ -132,10 +123,15 @@ impl ObjModel {
         })
     }
     
-    pub fn create_minecraft_tree(center: Vec3, size: f32, _material: Material) -> Self {
+    // The triangles in their own BVH, ready to be shared (Arc) between Instances
+    pub fn into_mesh(self) -> Mesh {
+        Mesh::new(self.triangles)
+    }
+    
+    pub fn create_minecraft_tree(center: Vec3, size: f32, materials: &mut MaterialTable) -> Self {
         let mut triangles: Vec<Box<dyn Primitive>> = Vec::new();
-        let trunk_material = Material::minecraft_wood();
-        let leaves_material = Material::minecraft_grass(); // Using grass for leaves
+        let trunk_material = materials.add(Material::minecraft_wood());
+        let leaves_material = materials.add(Material::minecraft_grass()); // Using grass for leaves
         
         let trunk_height = size * 0.6;
         let trunk_radius = size * 0.1;
@@ -159,8 +155,8 @@ impl ObjModel {
             let v3 = Vec3::new(x2, center.y + trunk_height, z2);
             let v4 = Vec3::new(x1, center.y + trunk_height, z1);
             
-            triangles.push(Box::new(Triangle::new(v1, v2, v3, trunk_material.clone())));
-            triangles.push(Box::new(Triangle::new(v1, v3, v4, trunk_material.clone())));
+            triangles.push(Box::new(Triangle::new(v1, v2, v3, trunk_material)));
+            triangles.push(Box::new(Triangle::new(v1, v3, v4, trunk_material)));
         }
         
         // Create leaves (simplified icosphere)
@@ -179,12 +175,12 @@ impl ObjModel {
         }
     }
     
-    pub fn create_minecraft_house(center: Vec3, size: f32) -> Self {
+    pub fn create_minecraft_house(center: Vec3, size: f32, materials: &mut MaterialTable) -> Self {
         let mut triangles: Vec<Box<dyn Primitive>> = Vec::new();
         
-        let wall_material = Material::minecraft_wood();
-        let roof_material = Material::minecraft_stone();
-        let window_material = Material::minecraft_glass();
+        let wall_material = materials.add(Material::minecraft_wood());
+        let roof_material = materials.add(Material::minecraft_stone());
+        let window_material = materials.add(Material::minecraft_glass());
         
         let half_size = size * 0.5;
         let height = size * 0.8;
@@ -206,8 +202,8 @@ impl ObjModel {
             let top2 = bottom2 + Vec3::new(0.0, height, 0.0);
             
             // Wall triangles
-            triangles.push(Box::new(Triangle::new(bottom1, bottom2, top2, wall_material.clone())));
-            triangles.push(Box::new(Triangle::new(bottom1, top2, top1, wall_material.clone())));
+            triangles.push(Box::new(Triangle::new(bottom1, bottom2, top2, wall_material)));
+            triangles.push(Box::new(Triangle::new(bottom1, top2, top1, wall_material)));
             
             // Add windows to front and back walls
             if i == 0 || i == 2 {
@@ -221,8 +217,8 @@ impl ObjModel {
                 let w3 = window_center + window_half;
                 let w4 = window_center + Vec3::new(-window_half.x, window_half.y, 0.0);
                 
-                triangles.push(Box::new(Triangle::new(w1, w2, w3, window_material.clone())));
-                triangles.push(Box::new(Triangle::new(w1, w3, w4, window_material.clone())));
+                triangles.push(Box::new(Triangle::new(w1, w2, w3, window_material)));
+                triangles.push(Box::new(Triangle::new(w1, w3, w4, window_material)));
             }
         }
         
@@ -234,13 +230,13 @@ impl ObjModel {
         let roof_base_4 = center + Vec3::new(-half_size, height, half_size);
         
         // Roof triangles
-        triangles.push(Box::new(Triangle::new(roof_peak, roof_base_1, roof_base_2, roof_material.clone())));
-        triangles.push(Box::new(Triangle::new(roof_peak, roof_base_2, roof_base_3, roof_material.clone())));
-        triangles.push(Box::new(Triangle::new(roof_peak, roof_base_3, roof_base_4, roof_material.clone())));
-        triangles.push(Box::new(Triangle::new(roof_peak, roof_base_4, roof_base_1, roof_material.clone())));
+        triangles.push(Box::new(Triangle::new(roof_peak, roof_base_1, roof_base_2, roof_material)));
+        triangles.push(Box::new(Triangle::new(roof_peak, roof_base_2, roof_base_3, roof_material)));
+        triangles.push(Box::new(Triangle::new(roof_peak, roof_base_3, roof_base_4, roof_material)));
+        triangles.push(Box::new(Triangle::new(roof_peak, roof_base_4, roof_base_1, roof_material)));
         
         // Floor
-        triangles.push(Box::new(Triangle::new(roof_base_1, roof_base_3, roof_base_2, wall_material.clone())));
+        triangles.push(Box::new(Triangle::new(roof_base_1, roof_base_3, roof_base_2, wall_material)));
         triangles.push(Box::new(Triangle::new(roof_base_1, roof_base_4, roof_base_3, wall_material)));
         
         let bounds_min = center - Vec3::new(half_size, 0.0, half_size);
@@ -253,11 +249,11 @@ impl ObjModel {
         }
     }
     
-    pub fn create_minecraft_windmill(center: Vec3, size: f32) -> Self {
+    pub fn create_minecraft_windmill(center: Vec3, size: f32, materials: &mut MaterialTable) -> Self {
         let mut triangles: Vec<Box<dyn Primitive>> = Vec::new();
         
-        let stone_material = Material::minecraft_stone();
-        let wood_material = Material::minecraft_wood();
+        let stone_material = materials.add(Material::minecraft_stone());
+        let wood_material = materials.add(Material::minecraft_wood());
         
         // Tower (cylinder)
         let tower_height = size * 1.5;
@@ -278,8 +274,8 @@ impl ObjModel {
             let top1 = Vec3::new(x1, center.y + tower_height, z1);
             let top2 = Vec3::new(x2, center.y + tower_height, z2);
             
-            triangles.push(Box::new(Triangle::new(bottom1, bottom2, top2, stone_material.clone())));
-            triangles.push(Box::new(Triangle::new(bottom1, top2, top1, stone_material.clone())));
+            triangles.push(Box::new(Triangle::new(bottom1, bottom2, top2, stone_material)));
+            triangles.push(Box::new(Triangle::new(bottom1, top2, top1, stone_material)));
         }
         
         // Windmill blades
@@ -306,8 +302,8 @@ impl ObjModel {
                 0.0,
             );
             
-            triangles.push(Box::new(Triangle::new(blade_center, blade_end, blade_side1, wood_material.clone())));
-            triangles.push(Box::new(Triangle::new(blade_center, blade_side2, blade_end, wood_material.clone())));
+            triangles.push(Box::new(Triangle::new(blade_center, blade_end, blade_side1, wood_material)));
+            triangles.push(Box::new(Triangle::new(blade_center, blade_side2, blade_end, wood_material)));
         }
         
         let bounds_min = center - Vec3::new(blade_length, 0.0, blade_length);
@@ -320,7 +316,7 @@ impl ObjModel {
         }
     }
     
-    fn create_icosphere(center: Vec3, radius: f32, _subdivisions: u32, material: Material) -> Vec<Box<dyn Primitive>> {
+    fn create_icosphere(center: Vec3, radius: f32, _subdivisions: u32, material: MaterialId) -> Vec<Box<dyn Primitive>> {
         let mut triangles: Vec<Box<dyn Primitive>> = Vec::new();
         
         // Start with icosahedron vertices
@@ -353,7 +349,7 @@ impl ObjModel {
             let v1 = vertices[face[1]];
             let v2 = vertices[face[2]];
             
-            triangles.push(Box::new(Triangle::new(v0, v1, v2, material.clone())));
+            triangles.push(Box::new(Triangle::new(v0, v1, v2, material)));
         }
         
         triangles
@@ -0,0 +1,247 @@
+use crate::materials::MaterialId;
+use crate::math::{hash, Ray, Vec3};
+use crate::raytracer::HitInfo;
+
+// Billboards are discs that always face the incoming ray (so the camera, for primary
+// rays); spheres are real tiny spheres with proper normals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParticleShape { Billboard, Sphere }
+
+// A stream of particles spawned at `rate` per second, each living `lifetime` seconds.
+// Every particle is a pure function of its spawn index and the time: a random start in the
+// spawn box, a jittered initial velocity, constant gravity and buoyancy, and an optional
+// swirl around `swirl_axis` through `origin`. So any frame can be evaluated directly.
+#[derive(Clone)]
+pub struct ParticleEmitter {
+    pub origin: Vec3,
+    pub spread: Vec3,   // Half-extents of the spawn box around `origin`
+    pub velocity: Vec3,
+    pub velocity_jitter: f32,
+    pub gravity: f32,   // Downward acceleration
+    pub buoyancy: f32,  // Upward acceleration, for hot sparks and smoke
+    pub swirl: f32,     // Angular speed (radians per second) around `swirl_axis`
+    pub swirl_axis: Vec3,
+    pub lifetime: f32,
+    pub rate: f32,
+    pub size: f32,      // Radius at birth
+    pub end_size: f32,  // Radius at the end of life
+    pub opacity: f32,   // Billboards below 1 fade out towards their rim, like puffs of smoke
+    pub shape: ParticleShape,
+    pub material: MaterialId,
+    pub cast_shadows: bool, // Glowing particles are light sources, not occluders
+}
+
+impl ParticleEmitter {
+    pub fn new(origin: Vec3, material: MaterialId) -> Self {
+        Self {
+            origin,
+            spread: Vec3::zero(),
+            velocity: Vec3::zero(),
+            velocity_jitter: 0.0,
+            gravity: 0.0,
+            buoyancy: 0.0,
+            swirl: 0.0,
+            swirl_axis: Vec3::new(0.0, 1.0, 0.0),
+            lifetime: 1.0,
+            rate: 10.0,
+            size: 0.03,
+            end_size: 0.03,
+            opacity: 1.0,
+            shape: ParticleShape::Sphere,
+            material,
+            cast_shadows: true,
+        }
+    }
+
+    pub fn with_spread(mut self, spread: Vec3) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec3, jitter: f32) -> Self {
+        self.velocity = velocity;
+        self.velocity_jitter = jitter;
+        self
+    }
+
+    pub fn with_forces(mut self, gravity: f32, buoyancy: f32) -> Self {
+        self.gravity = gravity;
+        self.buoyancy = buoyancy;
+        self
+    }
+
+    pub fn with_swirl(mut self, swirl: f32, axis: Vec3) -> Self {
+        self.swirl = swirl;
+        self.swirl_axis = axis.normalize();
+        self
+    }
+
+    pub fn with_lifetime(mut self, lifetime: f32, rate: f32) -> Self {
+        self.lifetime = lifetime.max(0.01);
+        self.rate = rate.max(0.01);
+        self
+    }
+
+    pub fn with_size(mut self, size: f32, end_size: f32) -> Self {
+        self.size = size;
+        self.end_size = end_size;
+        self
+    }
+
+    pub fn with_shape(mut self, shape: ParticleShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    // Position and radius of the particle spawned `index`-th, `age` seconds after its birth
+    fn particle(&self, index: f32, age: f32) -> Particle {
+        let random = |salt: f32| hash(Vec3::new(index, salt, 0.0));
+        let start = Vec3::new(random(1.0), random(2.0), random(3.0)) * self.spread;
+        let velocity = self.velocity + Vec3::new(random(4.0), random(5.0), random(6.0)) * self.velocity_jitter;
+        let acceleration = self.buoyancy - self.gravity;
+        let mut offset = start + velocity * age + Vec3::new(0.0, 0.5 * acceleration * age * age, 0.0);
+        if self.swirl != 0.0 {
+            offset = rotate_about(offset, self.swirl_axis, self.swirl * age);
+        }
+        let life = age / self.lifetime;
+        Particle {
+            position: self.origin + offset,
+            radius: self.size + (self.end_size - self.size) * life,
+        }
+    }
+
+    // Furthest any particle can get from `origin` during its life, per axis
+    fn reach(&self) -> Vec3 {
+        let t = self.lifetime;
+        let speed = self.velocity.abs() + Vec3::one() * self.velocity_jitter;
+        let drift = self.spread + speed * t + Vec3::new(0.0, 0.5 * (self.buoyancy - self.gravity).abs() * t * t, 0.0);
+        if self.swirl != 0.0 {
+            // Rotation can swing any offset onto any axis
+            Vec3::one() * drift.length()
+        } else {
+            drift
+        }
+        .max(Vec3::one() * 0.0) + Vec3::one() * self.size.max(self.end_size)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    position: Vec3,
+    radius: f32,
+}
+
+// The live particles of one emitter. `update` evaluates them for a frame's time (reusing
+// the buffer, so steady-state frames don't allocate); the tracer then intersects them after
+// the scene BVH, with a bounding-box test in front.
+pub struct ParticleSystem {
+    pub emitter: ParticleEmitter,
+    particles: Vec<Particle>,
+    bounds: (Vec3, Vec3),
+}
+
+impl ParticleSystem {
+    pub fn new(emitter: ParticleEmitter) -> Self {
+        let reach = emitter.reach();
+        let bounds = (emitter.origin - reach, emitter.origin + reach);
+        Self { emitter, particles: Vec::new(), bounds }
+    }
+
+    pub fn update(&mut self, time: f32) {
+        let emitter = &self.emitter;
+        self.particles.clear();
+        // Particle n is born at n / rate; the ones born within the last lifetime are alive
+        let newest = (time * emitter.rate).floor();
+        let oldest = ((time - emitter.lifetime) * emitter.rate).floor() + 1.0;
+        let mut index = oldest;
+        while index <= newest {
+            let age = time - index / emitter.rate;
+            self.particles.push(emitter.particle(index, age));
+            index += 1.0;
+        }
+    }
+
+    // Nearest particle along `ray` closer than `max_t`
+    pub fn intersect(&self, ray: &Ray, max_t: f32) -> Option<HitInfo> {
+        if !ray_hits_box(ray, self.bounds, max_t) {
+            return None;
+        }
+        let mut closest: Option<(f32, &Particle)> = None;
+        for particle in &self.particles {
+            let limit = closest.map_or(max_t, |(t, _)| t);
+            if let Some(t) = self.hit_distance(ray, particle).filter(|&t| t < limit) {
+                closest = Some((t, particle));
+            }
+        }
+        let (t, particle) = closest?;
+        let point = ray.point_at(t);
+        let normal = match self.emitter.shape {
+            ParticleShape::Billboard => -ray.direction.normalize(),
+            ParticleShape::Sphere => (point - particle.position).normalize(),
+        };
+        Some(HitInfo { t, point, normal, material: self.emitter.material, uv: (0.5, 0.5) })
+    }
+
+    pub fn occluded(&self, ray: &Ray, max_t: f32) -> bool {
+        self.emitter.cast_shadows
+            && ray_hits_box(ray, self.bounds, max_t)
+            && self.particles.iter().any(|particle| self.hit_distance(ray, particle).is_some_and(|t| t < max_t))
+    }
+
+    fn hit_distance(&self, ray: &Ray, particle: &Particle) -> Option<f32> {
+        let to_center = particle.position - ray.origin;
+        let length_squared = ray.direction.length_squared();
+        let t_center = to_center.dot(ray.direction) / length_squared;
+        let miss_squared = (to_center - ray.direction * t_center).length_squared();
+        let radius_squared = particle.radius * particle.radius;
+        if miss_squared > radius_squared {
+            return None;
+        }
+        let t = match self.emitter.shape {
+            // Translucent billboards are hit stochastically, so accumulated frames and soft
+            // shadows see their coverage rather than a refraction through a flat disc
+            ParticleShape::Billboard if self.emitter.opacity < 1.0 => {
+                let coverage = self.emitter.opacity * (1.0 - miss_squared / radius_squared);
+                if rand::random::<f32>() >= coverage {
+                    return None;
+                }
+                t_center
+            },
+            ParticleShape::Billboard => t_center,
+            ParticleShape::Sphere => t_center - ((radius_squared - miss_squared) / length_squared).sqrt(),
+        };
+        if t > 0.001 { Some(t) } else { None }
+    }
+}
+
+// Rodrigues' rotation of `v` by `angle` around the unit vector `axis`
+fn rotate_about(v: Vec3, axis: Vec3, angle: f32) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis.cross(v) * sin + axis * (axis.dot(v) * (1.0 - cos))
+}
+
+fn ray_hits_box(ray: &Ray, (min, max): (Vec3, Vec3), max_t: f32) -> bool {
+    let mut t_near = 0.0f32;
+    let mut t_far = max_t;
+    for (origin, direction, lo, hi) in [
+        (ray.origin.x, ray.direction.x, min.x, max.x),
+        (ray.origin.y, ray.direction.y, min.y, max.y),
+        (ray.origin.z, ray.direction.z, min.z, max.z),
+    ] {
+        let inv = 1.0 / direction;
+        let (t0, t1) = ((lo - origin) * inv, (hi - origin) * inv);
+        t_near = t_near.max(t0.min(t1));
+        t_far = t_far.min(t0.max(t1));
+    }
+    t_near <= t_far
+}
@@ -1,26 +1,41 @@
 use crate::math::*;
-use crate::materials::Material;
-use crate::raytracer::HitInfo;
+use crate::materials::{MaterialId, MaterialTable};
+use crate::raytracer::{build_bvh, BvhConfig, FlatBvh, HitInfo, Light, LightType};
+use std::sync::Arc;
 
 pub trait Primitive: Send + Sync {
     fn intersect(&self, ray: &Ray, time: f32) -> Option<HitInfo>;
     fn get_bounds(&self) -> (Vec3, Vec3);
+    
+    // Any-hit query for shadow rays: is there a surface between 0.001 and max_t?
+    // Primitives that can answer without building a HitInfo should override it
+    fn occluded(&self, ray: &Ray, max_t: f32, time: f32) -> bool {
+        self.intersect(ray, time).is_some_and(|hit| hit.t < max_t)
+    }
+    
+    // Area lights standing in for the emissive parts of this primitive (see
+    // `Scene::extract_emissive_lights`); shapes with no light equivalent add none
+    fn emitters(&self, _materials: &MaterialTable, _lights: &mut Vec<Light>) {}
+    
+    // Moves animated primitives to `time` once per frame (see `Scene::pose_objects`), so
+    // per-ray queries at that time can reuse the pose instead of rebuilding it
+    fn set_time(&mut self, _time: f32) {}
 }
 
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
-    pub material: Material,
+    pub material: MaterialId,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f32, material: Material) -> Self {
+    pub fn new(center: Vec3, radius: f32, material: MaterialId) -> Self {
         Self { center, radius, material }
     }
 }
 
-impl Primitive for Sphere {
-    fn intersect(&self, ray: &Ray, _time: f32) -> Option<HitInfo> {
+impl Sphere {
+    fn hit_distance(&self, ray: &Ray) -> Option<f32> {
         let oc = ray.origin - self.center;
         let a = ray.direction.dot(ray.direction);
         let b = 2.0 * oc.dot(ray.direction);
@@ -35,7 +50,13 @@ impl Primitive for Sphere {
         let t1 = (-b - sqrt_d) / (2.0 * a);
         let t2 = (-b + sqrt_d) / (2.0 * a);
         
-        let t = if t1 > 0.001 { t1 } else if t2 > 0.001 { t2 } else { return None; };
+        if t1 > 0.001 { Some(t1) } else if t2 > 0.001 { Some(t2) } else { None }
+    }
+}
+
+impl Primitive for Sphere {
+    fn intersect(&self, ray: &Ray, _time: f32) -> Option<HitInfo> {
+        let t = self.hit_distance(ray)?;
         
         let point = ray.point_at(t);
         let normal = (point - self.center).normalize();
@@ -50,26 +71,37 @@ impl Primitive for Sphere {
             t,
             point,
             normal,
-            material: self.material.clone(),
+            material: self.material,
             uv: (u, v),
         })
     }
     
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        let material = materials.get(self.material);
+        if material.is_emissive() {
+            lights.push(Light::emitter(self.center, LightType::Sphere { radius: self.radius }, material));
+        }
+    }
+    
     fn get_bounds(&self) -> (Vec3, Vec3) {
         let r = Vec3::new(self.radius, self.radius, self.radius);
         (self.center - r, self.center + r)
     }
+    
+    fn occluded(&self, ray: &Ray, max_t: f32, _time: f32) -> bool {
+        self.hit_distance(ray).is_some_and(|t| t < max_t)
+    }
 }
 
 pub struct Plane {
     pub point: Vec3,
     pub normal: Vec3,
-    pub material: Material,
+    pub material: MaterialId,
     pub size: Option<(f32, f32)>, // Optional size limits
 }
 
 impl Plane {
-    pub fn new(point: Vec3, normal: Vec3, material: Material) -> Self {
+    pub fn new(point: Vec3, normal: Vec3, material: MaterialId) -> Self {
         Self {
             point,
             normal: normal.normalize(),
@@ -127,20 +159,20 @@ impl Primitive for Plane {
             t,
             point,
             normal: self.normal,
-            material: self.material.clone(),
+            material: self.material,
             uv: (u, v),
         })
     }
     
     fn get_bounds(&self) -> (Vec3, Vec3) {
         // Return very large bounds for infinite plane
-        if self.size.is_none() {
-            (Vec3::new(-1000.0, -1000.0, -1000.0), Vec3::new(1000.0, 1000.0, 1000.0))
-        } else {
-            let (w, h) = self.size.unwrap();
-            let half_w = Vec3::new(w * 0.5, 0.0, 0.0);
-            let half_h = Vec3::new(0.0, h * 0.5, 0.0);
-            (self.point - half_w - half_h, self.point + half_w + half_h)
+        match self.size {
+            None => (Vec3::new(-1000.0, -1000.0, -1000.0), Vec3::new(1000.0, 1000.0, 1000.0)),
+            Some((w, h)) => {
+                let half_w = Vec3::new(w * 0.5, 0.0, 0.0);
+                let half_h = Vec3::new(0.0, h * 0.5, 0.0);
+                (self.point - half_w - half_h, self.point + half_w + half_h)
+            }
         }
     }
 }
@@ -148,53 +180,52 @@ impl Primitive for Plane {
 pub struct Cube {
     pub center: Vec3,
     pub size: f32,
-    pub material: Material,
-    pub rotation: Vec3, // Euler angles
+    pub material: MaterialId,
+    pub rotation: Vec3, // Euler angles, set through with_rotation
+    rotated: Option<Transform>, // Turn about the centre built from `rotation`
 }
 
 impl Cube {
-    pub fn new(center: Vec3, size: f32, material: Material) -> Self {
+    pub fn new(center: Vec3, size: f32, material: MaterialId) -> Self {
         Self {
             center,
             size,
             material,
             rotation: Vec3::zero(),
+            rotated: None,
         }
     }
     
+    // Turns the cube about its centre: z first, then x, then y, each in the sense of
+    // Vec3::rotate_x/y/z (rotate_y turns the opposite way to a right-handed quaternion)
     pub fn with_rotation(mut self, rotation: Vec3) -> Self {
         self.rotation = rotation;
+        self.rotated = (rotation != Vec3::zero())
+            .then(|| Transform::rotation_about(self.center, Quat::from_euler(Vec3::new(rotation.x, -rotation.y, rotation.z))));
         self
     }
-}
-
-impl Primitive for Cube {
-    fn intersect(&self, ray: &Ray, _time: f32) -> Option<HitInfo> {
-        // Transform ray to cube's local space if rotated
-        let (local_ray, inv_rotation) = if self.rotation != Vec3::zero() {
-            let inv_rot = -self.rotation;
-            let local_origin = (ray.origin - self.center).rotate_y(inv_rot.y).rotate_x(inv_rot.x).rotate_z(inv_rot.z);
-            let local_dir = ray.direction.rotate_y(inv_rot.y).rotate_x(inv_rot.x).rotate_z(inv_rot.z);
-            (Ray::new(local_origin + self.center, local_dir), Some(inv_rot))
-        } else {
-            (Ray::new(ray.origin, ray.direction), None)
-        };
-        
+    
+    // The same cube without its rotation, for intersecting in local space
+    fn aligned(&self) -> Cube {
+        Cube::new(self.center, self.size, self.material)
+    }
+    
+    fn hit_distance(&self, ray: &Ray) -> Option<f32> {
         let half_size = self.size / 2.0;
         let min = self.center - Vec3::new(half_size, half_size, half_size);
         let max = self.center + Vec3::new(half_size, half_size, half_size);
         
         // Ray-AABB intersection
-        let t_min_x = (min.x - local_ray.origin.x) / local_ray.direction.x;
-        let t_max_x = (max.x - local_ray.origin.x) / local_ray.direction.x;
+        let t_min_x = (min.x - ray.origin.x) / ray.direction.x;
+        let t_max_x = (max.x - ray.origin.x) / ray.direction.x;
         let (t_min_x, t_max_x) = if t_min_x > t_max_x { (t_max_x, t_min_x) } else { (t_min_x, t_max_x) };
         
-        let t_min_y = (min.y - local_ray.origin.y) / local_ray.direction.y;
-        let t_max_y = (max.y - local_ray.origin.y) / local_ray.direction.y;
+        let t_min_y = (min.y - ray.origin.y) / ray.direction.y;
+        let t_max_y = (max.y - ray.origin.y) / ray.direction.y;
         let (t_min_y, t_max_y) = if t_min_y > t_max_y { (t_max_y, t_min_y) } else { (t_min_y, t_max_y) };
         
-        let t_min_z = (min.z - local_ray.origin.z) / local_ray.direction.z;
-        let t_max_z = (max.z - local_ray.origin.z) / local_ray.direction.z;
+        let t_min_z = (min.z - ray.origin.z) / ray.direction.z;
+        let t_max_z = (max.z - ray.origin.z) / ray.direction.z;
         let (t_min_z, t_max_z) = if t_min_z > t_max_z { (t_max_z, t_min_z) } else { (t_min_z, t_max_z) };
         
         let t_min = t_min_x.max(t_min_y).max(t_min_z);
@@ -204,44 +235,41 @@ impl Primitive for Cube {
             return None;
         }
         
-        let t = if t_min > 0.001 { t_min } else if t_max > 0.001 { t_max } else { return None; };
+        if t_min > 0.001 { Some(t_min) } else if t_max > 0.001 { Some(t_max) } else { None }
+    }
+}
+
+impl Primitive for Cube {
+    fn intersect(&self, ray: &Ray, time: f32) -> Option<HitInfo> {
+        if let Some(rotated) = &self.rotated {
+            return rotated.intersect(&self.aligned(), ray, time);
+        }
+        let t = self.hit_distance(ray)?;
+        
+        let half_size = self.size / 2.0;
+        let min = self.center - Vec3::new(half_size, half_size, half_size);
+        let max = self.center + Vec3::new(half_size, half_size, half_size);
         
-        let local_point = local_ray.point_at(t);
+        let point = ray.point_at(t);
         
         // Calculate normal based on which face was hit
-        let mut normal;
         let eps = 0.0001;
-        
-        if (local_point.x - min.x).abs() < eps {
-            normal = Vec3::new(-1.0, 0.0, 0.0);
-        } else if (local_point.x - max.x).abs() < eps {
-            normal = Vec3::new(1.0, 0.0, 0.0);
-        } else if (local_point.y - min.y).abs() < eps {
-            normal = Vec3::new(0.0, -1.0, 0.0);
-        } else if (local_point.y - max.y).abs() < eps {
-            normal = Vec3::new(0.0, 1.0, 0.0);
-        } else if (local_point.z - min.z).abs() < eps {
-            normal = Vec3::new(0.0, 0.0, -1.0);
-        } else {
-            normal = Vec3::new(0.0, 0.0, 1.0);
-        }
-        
-        // Transform normal back to world space if rotated
-        if let Some(_) = inv_rotation {
-            normal = normal.rotate_z(self.rotation.z).rotate_x(self.rotation.x).rotate_y(self.rotation.y);
-        }
-        
-        // Transform point back to world space
-        let world_point = if inv_rotation.is_some() {
-            let local_offset = local_point - self.center;
-            let world_offset = local_offset.rotate_z(self.rotation.z).rotate_x(self.rotation.x).rotate_y(self.rotation.y);
-            self.center + world_offset
+        let normal = if (point.x - min.x).abs() < eps {
+            Vec3::new(-1.0, 0.0, 0.0)
+        } else if (point.x - max.x).abs() < eps {
+            Vec3::new(1.0, 0.0, 0.0)
+        } else if (point.y - min.y).abs() < eps {
+            Vec3::new(0.0, -1.0, 0.0)
+        } else if (point.y - max.y).abs() < eps {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else if (point.z - min.z).abs() < eps {
+            Vec3::new(0.0, 0.0, -1.0)
         } else {
-            local_point
+            Vec3::new(0.0, 0.0, 1.0)
         };
         
         // UV mapping for cube faces
-        let local_point_centered = local_point - self.center;
+        let local_point_centered = point - self.center;
         let (u, v) = if normal.x.abs() > 0.5 {
             // X face
             ((local_point_centered.z + half_size) / self.size, (local_point_centered.y + half_size) / self.size)
@@ -255,23 +283,39 @@ impl Primitive for Cube {
         
         Some(HitInfo {
             t,
-            point: world_point,
+            point,
             normal,
-            material: self.material.clone(),
+            material: self.material,
             uv: (u, v),
         })
     }
     
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        let material = materials.get(self.material);
+        if material.is_emissive() {
+            let half = self.size / 2.0;
+            let light = Light::emitter(self.center, LightType::Box { half_extents: Vec3::new(half, half, half) }, material);
+            lights.push(match &self.rotated {
+                Some(rotated) => rotated.light_to_world(light),
+                None => light,
+            });
+        }
+    }
+    
     fn get_bounds(&self) -> (Vec3, Vec3) {
         let half_size = self.size / 2.0;
         let extent = Vec3::new(half_size, half_size, half_size);
-        
-        if self.rotation == Vec3::zero() {
-            (self.center - extent, self.center + extent)
-        } else {
-            // For rotated cubes, compute conservative bounds
-            let expanded = extent * 1.73; // sqrt(3) for worst case rotation
-            (self.center - expanded, self.center + expanded)
+        let bounds = (self.center - extent, self.center + extent);
+        match &self.rotated {
+            Some(rotated) => rotated.bounds_to_world(bounds),
+            None => bounds,
+        }
+    }
+    
+    fn occluded(&self, ray: &Ray, max_t: f32, time: f32) -> bool {
+        match &self.rotated {
+            Some(rotated) => rotated.occluded(&self.aligned(), ray, max_t, time),
+            None => self.hit_distance(ray).is_some_and(|t| t < max_t),
         }
     }
 }
@@ -280,84 +324,264 @@ impl Primitive for Cube {
 pub struct SpinningCube {
     pub center: Vec3,
     pub size: f32,
-    pub material: Material,
+    pub material: MaterialId,
     pub rotation_speed: f32,
+    pose: (f32, Transform), // Time of the last set_time and the turn reached then
 }
 
 impl SpinningCube {
-    pub fn new(center: Vec3, size: f32, material: Material, rotation_speed: f32) -> Self {
-        Self { center, size, material, rotation_speed }
+    pub fn new(center: Vec3, size: f32, material: MaterialId, rotation_speed: f32) -> Self {
+        let mut cube = Self {
+            center,
+            size,
+            material,
+            rotation_speed,
+            pose: (0.0, Transform { matrix: Mat4::identity(), inverse: Mat4::identity() }),
+        };
+        cube.set_time(0.0);
+        cube
+    }
+    
+    // The turn about the centre reached at `time`
+    fn turn_at(&self, time: f32) -> Transform {
+        // Negated: the cube has always spun the way Vec3::rotate_y turns
+        let angle = -time * self.rotation_speed;
+        Transform::rotation_about(self.center, Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), angle))
+    }
+    
+    // The unturned cube and its turn at `time`, cached when `time` is the posed frame's
+    fn posed(&self, time: f32) -> (Cube, Transform) {
+        let (posed_time, turn) = self.pose;
+        let turn = if time == posed_time { turn } else { self.turn_at(time) };
+        (Cube::new(self.center, self.size, self.material), turn)
     }
 }
 
 impl Primitive for SpinningCube {
     fn intersect(&self, ray: &Ray, time: f32) -> Option<HitInfo> {
-        // Compute time-based rotation only on Y axis
-        let angle = time * self.rotation_speed;
-        let inv_rot = Vec3::new(0.0, -angle, 0.0);
-        let local_origin = (ray.origin - self.center).rotate_y(inv_rot.y);
-        let local_dir = ray.direction.rotate_y(inv_rot.y);
-        let local_ray = Ray::new(local_origin + self.center, local_dir);
-
+        let (cube, turn) = self.posed(time);
+        turn.intersect(&cube, ray, time)
+    }
+    
+    fn occluded(&self, ray: &Ray, max_t: f32, time: f32) -> bool {
+        let (cube, turn) = self.posed(time);
+        turn.occluded(&cube, ray, max_t, time)
+    }
+    
+    fn set_time(&mut self, time: f32) {
+        self.pose = (time, self.turn_at(time));
+    }
+    
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        let material = materials.get(self.material);
+        if material.is_emissive() {
+            // Spinning, so a sphere of the same volume stands in for it
+            let radius = self.size * (3.0 / (4.0 * std::f32::consts::PI)).cbrt();
+            lights.push(Light::emitter(self.center, LightType::Sphere { radius }, material));
+        }
+    }
+    
+    fn get_bounds(&self) -> (Vec3, Vec3) {
+        // Covers every angle: the cube's corners sweep a circle in xz
         let half_size = self.size / 2.0;
-        let min = self.center - Vec3::new(half_size, half_size, half_size);
-        let max = self.center + Vec3::new(half_size, half_size, half_size);
+        let reach = half_size * std::f32::consts::SQRT_2;
+        let extent = Vec3::new(reach, half_size, reach);
+        (self.center - extent, self.center + extent)
+    }
+}
 
-        // Ray-AABB intersection
-        let t_min_x = (min.x - local_ray.origin.x) / local_ray.direction.x;
-        let t_max_x = (max.x - local_ray.origin.x) / local_ray.direction.x;
-        let (t_min_x, t_max_x) = if t_min_x > t_max_x { (t_max_x, t_min_x) } else { (t_min_x, t_max_x) };
-        
-        let t_min_y = (min.y - local_ray.origin.y) / local_ray.direction.y;
-        let t_max_y = (max.y - local_ray.origin.y) / local_ray.direction.y;
-        let (t_min_y, t_max_y) = if t_min_y > t_max_y { (t_max_y, t_min_y) } else { (t_min_y, t_max_y) };
-        
-        let t_min_z = (min.z - local_ray.origin.z) / local_ray.direction.z;
-        let t_max_z = (max.z - local_ray.origin.z) / local_ray.direction.z;
-        let (t_min_z, t_max_z) = if t_min_z > t_max_z { (t_max_z, t_min_z) } else { (t_min_z, t_max_z) };
-        
-        let t_min = t_min_x.max(t_min_y).max(t_min_z);
-        let t_max = t_max_x.min(t_max_y).min(t_max_z);
-        
-        if t_max < 0.0 || t_min > t_max { return None; }
-        let t = if t_min > 0.001 { t_min } else if t_max > 0.001 { t_max } else { return None; };
-        
-        let local_point = local_ray.point_at(t);
-        // Determine normal in local space
-        let mut normal;
-        let eps = 0.0001;
-        if (local_point.x - min.x).abs() < eps { normal = Vec3::new(-1.0, 0.0, 0.0); }
-        else if (local_point.x - max.x).abs() < eps { normal = Vec3::new(1.0, 0.0, 0.0); }
-        else if (local_point.y - min.y).abs() < eps { normal = Vec3::new(0.0, -1.0, 0.0); }
-        else if (local_point.y - max.y).abs() < eps { normal = Vec3::new(0.0, 1.0, 0.0); }
-        else if (local_point.z - min.z).abs() < eps { normal = Vec3::new(0.0, 0.0, -1.0); }
-        else { normal = Vec3::new(0.0, 0.0, 1.0); }
-
-        // Transform normal back to world by current rotation
-        normal = normal.rotate_y(angle);
-
-        // Transform point back
-        let local_offset = local_point - self.center;
-        let world_offset = local_offset.rotate_y(angle);
-        let world_point = self.center + world_offset;
-
-        // UV mapping as in Cube
-        let local_point_centered = local_point - self.center;
-        let (u, v) = if normal.x.abs() > 0.5 {
-            ((local_point_centered.z + half_size) / self.size, (local_point_centered.y + half_size) / self.size)
-        } else if normal.y.abs() > 0.5 {
-            ((local_point_centered.x + half_size) / self.size, (local_point_centered.z + half_size) / self.size)
-        } else {
-            ((local_point_centered.x + half_size) / self.size, (local_point_centered.y + half_size) / self.size)
+// An invertible affine transform stored with its inverse, plus what instancing needs:
+// rays into object space, and hits, normals and bounds back out to world space
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    pub matrix: Mat4,
+    pub inverse: Mat4,
+}
+
+impl Transform {
+    pub fn new(matrix: Mat4) -> Option<Self> {
+        Some(Self { matrix, inverse: matrix.inverse()? })
+    }
+    
+    // A turn about `center`; pure rotations invert by turning back, so no matrix inverse
+    pub fn rotation_about(center: Vec3, rotation: Quat) -> Self {
+        let back = Quat { w: rotation.w, x: -rotation.x, y: -rotation.y, z: -rotation.z };
+        let about_center = |rotation: Quat| Mat4::translation(center) * Mat4::rotation(rotation) * Mat4::translation(-center);
+        Self { matrix: about_center(rotation), inverse: about_center(back) }
+    }
+    
+    // The ray in object space, and how many object-space units one unit of the world ray's
+    // `t` covers (Ray::new normalizes the local direction, and scaling changes lengths)
+    fn local_ray(&self, ray: &Ray) -> (Ray, f32) {
+        let direction = self.inverse.transform_vector(ray.direction);
+        let scale = direction.length();
+        (Ray::new(self.inverse.transform_point(ray.origin), direction), scale)
+    }
+    
+    // Normals go through the inverse transpose so they stay perpendicular under scaling
+    fn normal_to_world(&self, n: Vec3) -> Vec3 {
+        let m = &self.inverse.m;
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        )
+        .normalize()
+    }
+    
+    pub fn intersect(&self, object: &(impl Primitive + ?Sized), ray: &Ray, time: f32) -> Option<HitInfo> {
+        let (local_ray, scale) = self.local_ray(ray);
+        let hit = object.intersect(&local_ray, time)?;
+        let t = hit.t / scale;
+        Some(HitInfo {
+            t,
+            point: ray.point_at(t),
+            normal: self.normal_to_world(hit.normal),
+            material: hit.material,
+            uv: hit.uv,
+        })
+    }
+    
+    pub fn occluded(&self, object: &(impl Primitive + ?Sized), ray: &Ray, max_t: f32, time: f32) -> bool {
+        let (local_ray, scale) = self.local_ray(ray);
+        object.occluded(&local_ray, max_t * scale, time)
+    }
+    
+    // Box around the eight transformed corners of an object-space box
+    pub fn bounds_to_world(&self, (min, max): (Vec3, Vec3)) -> (Vec3, Vec3) {
+        let mut world_min = Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut world_max = -world_min;
+        for corner in 0..8 {
+            let p = Vec3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            );
+            let world = self.matrix.transform_point(p);
+            world_min = world_min.min(world);
+            world_max = world_max.max(world);
+        }
+        (world_min, world_max)
+    }
+    
+    // Moves an object-space light into world space. Emitted power keeps its object-space
+    // value, so scaled emitters are only approximately as bright as their new area
+    fn light_to_world(&self, light: Light) -> Light {
+        let scale = |v: Vec3| self.matrix.transform_vector(v);
+        let uniform_scale = (scale(Vec3::new(1.0, 1.0, 1.0)).length() / 3f32.sqrt()).max(1e-6);
+        let light_type = match light.light_type {
+            LightType::Point => LightType::Point,
+            LightType::Directional(direction) => LightType::Directional(scale(direction).normalize()),
+            LightType::Spot { direction, inner_cone, outer_cone } => LightType::Spot { direction: scale(direction).normalize(), inner_cone, outer_cone },
+            LightType::Sun { direction, angular_diameter } => LightType::Sun { direction: scale(direction).normalize(), angular_diameter },
+            LightType::Rect { half_u, half_v } => LightType::Rect { half_u: scale(half_u), half_v: scale(half_v) },
+            LightType::Sphere { radius } => LightType::Sphere { radius: radius * uniform_scale },
+            LightType::Disk { normal, radius } => LightType::Disk { normal: self.normal_to_world(normal), radius: radius * uniform_scale },
+            LightType::Box { half_extents } => {
+                let (min, max) = self.bounds_to_world((-half_extents, half_extents));
+                LightType::Box { half_extents: (max - min) * 0.5 }
+            },
         };
+        Light { position: self.matrix.transform_point(light.position), light_type, ..light }
+    }
+}
+
+// Any primitive placed in the world by a 4x4 transform. Rays are taken into object space,
+// so the wrapped primitive keeps its simple local intersection; wrap an `Arc<Mesh>` to
+// place one mesh many times without copying its triangles.
+pub struct Instance<P> {
+    pub object: P,
+    transform: Transform,
+    bounds: (Vec3, Vec3),
+}
+
+impl<P: Primitive> Instance<P> {
+    pub fn new(object: P, matrix: Mat4) -> Self {
+        let transform = Transform::new(matrix).expect("instance transform must be invertible");
+        let bounds = transform.bounds_to_world(object.get_bounds());
+        Self { object, transform, bounds }
+    }
+}
+
+impl<P: Primitive> Primitive for Instance<P> {
+    fn intersect(&self, ray: &Ray, time: f32) -> Option<HitInfo> {
+        self.transform.intersect(&self.object, ray, time)
+    }
+    
+    fn occluded(&self, ray: &Ray, max_t: f32, time: f32) -> bool {
+        self.transform.occluded(&self.object, ray, max_t, time)
+    }
+    
+    fn get_bounds(&self) -> (Vec3, Vec3) {
+        self.bounds
+    }
+    
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        let mut local = Vec::new();
+        self.object.emitters(materials, &mut local);
+        lights.extend(local.into_iter().map(|light| self.transform.light_to_world(light)));
+    }
+    
+    fn set_time(&mut self, time: f32) {
+        self.object.set_time(time);
+    }
+}
+
+// Shared ownership, so several instances can reference one mesh
+impl<P: Primitive + ?Sized> Primitive for Arc<P> {
+    fn intersect(&self, ray: &Ray, time: f32) -> Option<HitInfo> {
+        (**self).intersect(ray, time)
+    }
+    
+    fn occluded(&self, ray: &Ray, max_t: f32, time: f32) -> bool {
+        (**self).occluded(ray, max_t, time)
+    }
+    
+    fn get_bounds(&self) -> (Vec3, Vec3) {
+        (**self).get_bounds()
+    }
+    
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        (**self).emitters(materials, lights)
+    }
+}
+
+// A group of primitives (typically an OBJ's triangles) with its own BVH, so it can be
+// instanced as a single object
+pub struct Mesh {
+    objects: Vec<Box<dyn Primitive>>,
+    bvh: Option<FlatBvh>,
+    bounds: (Vec3, Vec3),
+}
 
-        Some(HitInfo { t, point: world_point, normal, material: self.material.clone(), uv: (u, v) })
+impl Mesh {
+    pub fn new(objects: Vec<Box<dyn Primitive>>) -> Self {
+        let bvh = build_bvh(&objects, &BvhConfig::default()).map(|tree| FlatBvh::from_tree(&tree));
+        let bounds = objects.iter().map(|object| object.get_bounds()).fold(
+            (Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY), Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)),
+            |(min, max), (object_min, object_max)| (min.min(object_min), max.max(object_max)),
+        );
+        Self { objects, bvh, bounds }
     }
+}
 
+impl Primitive for Mesh {
+    fn intersect(&self, ray: &Ray, time: f32) -> Option<HitInfo> {
+        self.bvh.as_ref()?.intersect(ray, &self.objects, time)
+    }
+    
+    fn occluded(&self, ray: &Ray, max_t: f32, time: f32) -> bool {
+        self.bvh.as_ref().is_some_and(|bvh| bvh.occluded(ray, max_t, &self.objects, time))
+    }
+    
     fn get_bounds(&self) -> (Vec3, Vec3) {
-        let half_size = self.size / 2.0;
-        let extent = Vec3::new(half_size, half_size, half_size) * 1.73; // conservative bounds
-        (self.center - extent, self.center + extent)
+        self.bounds
+    }
+    
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        for object in &self.objects {
+            object.emitters(materials, lights);
+        }
     }
 }
 
@@ -366,14 +590,14 @@ pub struct Triangle {
     pub v1: Vec3,
     pub v2: Vec3,
     pub normal: Vec3,
-    pub material: Material,
+    pub material: MaterialId,
     pub uv0: (f32, f32),
     pub uv1: (f32, f32),
     pub uv2: (f32, f32),
 }
 
 impl Triangle {
-    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: Material) -> Self {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: MaterialId) -> Self {
         let normal = (v1 - v0).cross(v2 - v0).normalize();
         Triangle {
             v0, v1, v2, normal, material,
@@ -386,7 +610,7 @@ impl Triangle {
     pub fn new_with_uvs(
         v0: Vec3, v1: Vec3, v2: Vec3,
         uv0: (f32, f32), uv1: (f32, f32), uv2: (f32, f32),
-        material: Material
+        material: MaterialId
     ) -> Self {
         let normal = (v1 - v0).cross(v2 - v0).normalize();
         Triangle {
@@ -395,7 +619,7 @@ impl Triangle {
         }
     }
     
-    pub fn new_with_normal(v0: Vec3, v1: Vec3, v2: Vec3, normal: Vec3, material: Material) -> Self {
+    pub fn new_with_normal(v0: Vec3, v1: Vec3, v2: Vec3, normal: Vec3, material: MaterialId) -> Self {
         Triangle {
             v0, v1, v2, 
             normal: normal.normalize(), 
@@ -407,8 +631,9 @@ impl Triangle {
     }
 }
 
-impl Primitive for Triangle {
-    fn intersect(&self, ray: &Ray, _time: f32) -> Option<HitInfo> {
+impl Triangle {
+    // Distance and barycentric (u, v) of the hit
+    fn hit_distance(&self, ray: &Ray) -> Option<(f32, f32, f32)> {
         // Möller-Trumbore intersection algorithm
         let edge1 = self.v1 - self.v0;
         let edge2 = self.v2 - self.v0;
@@ -423,7 +648,7 @@ impl Primitive for Triangle {
         let s = ray.origin - self.v0;
         let u = f * s.dot(h);
         
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
         
@@ -440,6 +665,13 @@ impl Primitive for Triangle {
             return None;
         }
         
+        Some((t, u, v))
+    }
+}
+
+impl Primitive for Triangle {
+    fn intersect(&self, ray: &Ray, _time: f32) -> Option<HitInfo> {
+        let (t, u, v) = self.hit_distance(ray)?;
         let point = ray.point_at(t);
         
         // Interpolate UV coordinates
@@ -451,11 +683,22 @@ impl Primitive for Triangle {
             t,
             point,
             normal: self.normal,
-            material: self.material.clone(),
+            material: self.material,
             uv: (interpolated_u, interpolated_v),
         })
     }
     
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        let material = materials.get(self.material);
+        if material.is_emissive() {
+            // Disk of the same area at the centroid
+            let area = (self.v1 - self.v0).cross(self.v2 - self.v0).length() * 0.5;
+            let radius = (area / std::f32::consts::PI).sqrt();
+            let centroid = (self.v0 + self.v1 + self.v2) / 3.0;
+            lights.push(Light::emitter(centroid, LightType::Disk { normal: self.normal, radius }, material));
+        }
+    }
+    
     fn get_bounds(&self) -> (Vec3, Vec3) {
         let min_x = self.v0.x.min(self.v1.x).min(self.v2.x);
         let min_y = self.v0.y.min(self.v1.y).min(self.v2.y);
@@ -467,6 +710,10 @@ impl Primitive for Triangle {
         
         (Vec3::new(min_x, min_y, min_z), Vec3::new(max_x, max_y, max_z))
     }
+    
+    fn occluded(&self, ray: &Ray, max_t: f32, _time: f32) -> bool {
+        self.hit_distance(ray).is_some_and(|(t, _, _)| t < max_t)
+    }
 }
 
 // Cylinder primitive for more variety
@@ -474,11 +721,11 @@ pub struct Cylinder {
     pub center: Vec3,
     pub radius: f32,
     pub height: f32,
-    pub material: Material,
+    pub material: MaterialId,
 }
 
 impl Cylinder {
-    pub fn new(center: Vec3, radius: f32, height: f32, material: Material) -> Self {
+    pub fn new(center: Vec3, radius: f32, height: f32, material: MaterialId) -> Self {
         Self { center, radius, height, material }
     }
 }
@@ -522,7 +769,7 @@ impl Primitive for Cylinder {
                         t,
                         point,
                         normal,
-                        material: self.material.clone(),
+                        material: self.material,
                         uv: (u, v),
                     });
                 }
@@ -548,7 +795,7 @@ impl Primitive for Cylinder {
                         t,
                         point,
                         normal,
-                        material: self.material.clone(),
+                        material: self.material,
                         uv: (u, v),
                     });
                 }
@@ -558,6 +805,14 @@ impl Primitive for Cylinder {
         None
     }
     
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        let material = materials.get(self.material);
+        if material.is_emissive() {
+            let (min, max) = self.get_bounds();
+            lights.push(Light::emitter((min + max) * 0.5, LightType::Box { half_extents: (max - min) * 0.5 }, material));
+        }
+    }
+    
     fn get_bounds(&self) -> (Vec3, Vec3) {
         let half_height = self.height * 0.5;
         (
@@ -572,72 +827,90 @@ pub struct Torus {
     pub center: Vec3,
     pub major_radius: f32,
     pub minor_radius: f32,
-    pub material: Material,
+    pub material: MaterialId,
 }
 
 impl Torus {
-    pub fn new(center: Vec3, major_radius: f32, minor_radius: f32, material: Material) -> Self {
+    pub fn new(center: Vec3, major_radius: f32, minor_radius: f32, material: MaterialId) -> Self {
         Self { center, major_radius, minor_radius, material }
     }
 }
 
-impl Primitive for Torus {
-    fn intersect(&self, ray: &Ray, _time: f32) -> Option<HitInfo> {
-        // Transform ray to torus local space
+impl Torus {
+    // Nearest hit past 0.001: the quartic (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) along
+    // the ray, solved exactly in f64. The ray starts at the bounding sphere so distant
+    // tori don't lose precision and rays that miss it cost almost nothing.
+    fn hit_distance(&self, ray: &Ray) -> Option<f32> {
+        let length = ray.direction.length() as f64;
+        let direction = ray.direction / length as f32;
         let oc = ray.origin - self.center;
+        let (big_r, r) = (self.major_radius as f64, self.minor_radius as f64);
         
-        // Torus intersection is complex - using approximation for performance
-        // This is a simplified version that works reasonably well
-        let R = self.major_radius;
-        let r = self.minor_radius;
-        
-        // Coefficients for quartic equation (simplified)
-        let ox = oc.x;
-        let oy = oc.y;
-        let oz = oc.z;
-        let dx = ray.direction.x;
-        let dy = ray.direction.y;
-        let dz = ray.direction.z;
-        
-        let _sum_d_sqr = dx*dx + dy*dy + dz*dz;
-        let _e = ox*ox + oy*oy + oz*oz - R*R - r*r;
-        let _f = ox*dx + oy*dy + oz*dz;
-        let _four_a_sqr = 4.0 * R*R;
-        
-        // Simplified quartic solving - use iterative method
-        for i in 0..100 {
-            let t = 0.1 + i as f32 * 0.1;
-            let point = ray.point_at(t);
-            let local = point - self.center;
-            
-            let xy_dist = (local.x*local.x + local.z*local.z).sqrt();
-            let torus_center_dist = (xy_dist - R).abs();
-            let torus_surface_dist = (torus_center_dist*torus_center_dist + local.y*local.y).sqrt();
-            
-            if torus_surface_dist <= r && t > 0.001 {
-                // Approximate normal
-                let torus_center = Vec3::new(
-                    local.x / xy_dist * R,
-                    0.0,
-                    local.z / xy_dist * R
-                );
-                let normal = (local - torus_center).normalize();
-                
-                // Torus UV mapping (simplified)
-                let u = (local.z.atan2(local.x) + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
-                let v = (local.y.atan2(xy_dist - R) + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
-                
-                return Some(HitInfo {
-                    t,
-                    point,
-                    normal,
-                    material: self.material.clone(),
-                    uv: (u, v),
-                });
-            }
+        // Skip ahead to the bounding sphere
+        let bound = big_r + r;
+        let b = oc.dot(direction) as f64;
+        let c = oc.length_squared() as f64 - bound * bound;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let t_exit = -b + discriminant.sqrt();
+        if t_exit <= 0.0 {
+            return None;
         }
+        let t_start = (-b - discriminant.sqrt()).max(0.0);
         
-        None
+        let origin = oc + direction * t_start as f32;
+        let (ox, oy, oz) = (origin.x as f64, origin.y as f64, origin.z as f64);
+        let (dx, dy, dz) = (direction.x as f64, direction.y as f64, direction.z as f64);
+        let m = ox * ox + oy * oy + oz * oz;
+        let n = ox * dx + oy * dy + oz * dz;
+        let k = m + big_r * big_r - r * r;
+        let four_r2 = 4.0 * big_r * big_r;
+        let (roots, count) = solve_quartic(
+            1.0,
+            4.0 * n,
+            4.0 * n * n + 2.0 * k - four_r2 * (dx * dx + dz * dz),
+            4.0 * n * k - 2.0 * four_r2 * (ox * dx + oz * dz),
+            k * k - four_r2 * (ox * ox + oz * oz),
+        );
+        
+        roots[..count].iter()
+            .map(|&t| ((t + t_start) / length) as f32)
+            .find(|&t| t > 0.001)
+    }
+}
+
+impl Primitive for Torus {
+    fn intersect(&self, ray: &Ray, _time: f32) -> Option<HitInfo> {
+        let t = self.hit_distance(ray)?;
+        let point = ray.point_at(t);
+        let local = point - self.center;
+        
+        // The normal points away from the nearest point on the tube's centre circle
+        let ring_dist = (local.x * local.x + local.z * local.z).sqrt();
+        let ring_point = if ring_dist > 1e-6 {
+            Vec3::new(local.x, 0.0, local.z) * (self.major_radius / ring_dist)
+        } else {
+            Vec3::zero()
+        };
+        let normal = (local - ring_point).normalize();
+        
+        // u around the main ring, v around the tube
+        let u = (local.z.atan2(local.x) + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
+        let v = (local.y.atan2(ring_dist - self.major_radius) + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
+        
+        Some(HitInfo {
+            t,
+            point,
+            normal,
+            material: self.material,
+            uv: (u, v),
+        })
+    }
+    
+    fn occluded(&self, ray: &Ray, max_t: f32, _time: f32) -> bool {
+        self.hit_distance(ray).is_some_and(|t| t < max_t)
     }
     
     fn get_bounds(&self) -> (Vec3, Vec3) {
@@ -647,4 +920,186 @@ impl Primitive for Torus {
             self.center + Vec3::new(outer_radius, self.minor_radius, outer_radius)
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn torus() -> Torus {
+        Torus::new(Vec3::zero(), 2.0, 0.5, 0)
+    }
+    
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "expected {expected}, got {actual}");
+    }
+    
+    #[test]
+    fn quartic_with_four_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let (roots, count) = solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0);
+        assert_eq!(count, 4);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected).abs() < 1e-9, "root {root}");
+        }
+    }
+    
+    #[test]
+    fn quartic_without_real_roots() {
+        let (_, count) = solve_quartic(1.0, 0.0, 1.0, 0.0, 1.0);
+        assert_eq!(count, 0);
+    }
+    
+    #[test]
+    fn torus_hit_along_the_equator() {
+        let hit = torus().intersect(&Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 0.0).unwrap();
+        assert_close(hit.t, 2.5, 1e-4);
+        assert_close(hit.point.x, -2.5, 1e-4);
+        assert_close(hit.normal.x, -1.0, 1e-4);
+    }
+    
+    #[test]
+    fn torus_hit_from_above_has_up_normal_and_uv() {
+        let hit = torus().intersect(&Ray::new(Vec3::new(0.0, 5.0, 2.0), Vec3::new(0.0, -1.0, 0.0)), 0.0).unwrap();
+        assert_close(hit.t, 4.5, 1e-4);
+        assert_close(hit.normal.y, 1.0, 1e-4);
+        assert_close(hit.uv.0, 0.75, 1e-4);
+        assert_close(hit.uv.1, 0.75, 1e-4);
+    }
+    
+    #[test]
+    fn torus_hit_at_an_angle() {
+        // Aim at the point on the tube 45 degrees above the outer equator
+        let target = Vec3::new(2.0 + 0.5 * std::f32::consts::FRAC_1_SQRT_2, 0.5 * std::f32::consts::FRAC_1_SQRT_2, 0.0);
+        let origin = Vec3::new(6.0, 4.0, 0.0);
+        let hit = torus().intersect(&Ray::new(origin, (target - origin).normalize()), 0.0).unwrap();
+        assert_close(hit.t, (target - origin).length(), 1e-3);
+        assert_close(hit.normal.x, std::f32::consts::FRAC_1_SQRT_2, 1e-3);
+        assert_close(hit.normal.y, std::f32::consts::FRAC_1_SQRT_2, 1e-3);
+    }
+    
+    #[test]
+    fn torus_ray_through_the_hole_misses() {
+        let ray = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus().intersect(&ray, 0.0).is_none());
+        let behind = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(torus().intersect(&behind, 0.0).is_none());
+    }
+    
+    #[test]
+    fn torus_hit_far_away() {
+        let hit = torus().intersect(&Ray::new(Vec3::new(-1000.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 0.0).unwrap();
+        assert_close(hit.t, 997.5, 1e-2);
+    }
+    
+    #[test]
+    fn thin_torus_is_not_missed() {
+        let thin = Torus::new(Vec3::new(1.0, 2.0, 3.0), 3.0, 0.01, 0);
+        let hit = thin.intersect(&Ray::new(Vec3::new(1.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0)), 0.0).unwrap();
+        assert_close(hit.t, 4.99, 1e-4);
+    }
+    
+    #[test]
+    fn torus_hit_from_inside_the_tube() {
+        // From inside the tube the first hit is its inner wall
+        let inside = torus().intersect(&Ray::new(Vec3::new(-2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 0.0).unwrap();
+        assert_close(inside.t, 0.5, 1e-4);
+        assert_close(inside.normal.x, 1.0, 1e-4);
+    }
+    
+    #[test]
+    fn torus_occlusion_respects_max_distance() {
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(torus().occluded(&ray, 3.0, 0.0));
+        assert!(!torus().occluded(&ray, 2.0, 0.0));
+    }
+    
+    #[test]
+    fn scaled_instance_hits_at_world_distance() {
+        // A unit sphere stretched to an ellipsoid with x radius 3, moved to x = 10
+        let sphere = Sphere::new(Vec3::zero(), 1.0, 0);
+        let instance = Instance::new(sphere, Mat4::from_trs(Vec3::new(10.0, 0.0, 0.0), Quat::identity(), Vec3::new(3.0, 1.0, 1.0)));
+        let hit = instance.intersect(&Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0)), 0.0).unwrap();
+        assert_close(hit.t, 7.0, 1e-4);
+        assert_close(hit.point.x, 7.0, 1e-4);
+        assert_close(hit.normal.x, -1.0, 1e-4);
+        assert!(instance.occluded(&Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0)), 7.5, 0.0));
+        assert!(!instance.occluded(&Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0)), 6.5, 0.0));
+    }
+    
+    #[test]
+    fn instance_normals_use_inverse_transpose() {
+        // On the ellipsoid x^2/4 + y^2 = 1 the normal at (sqrt 2, sqrt 0.5) is along (1, 2)
+        let instance = Instance::new(Sphere::new(Vec3::zero(), 1.0, 0), Mat4::scale(Vec3::new(2.0, 1.0, 1.0)));
+        let target = Vec3::new(2f32.sqrt(), 0.5f32.sqrt(), 0.0);
+        let origin = target + Vec3::new(0.0, 5.0, 0.0);
+        let hit = instance.intersect(&Ray::new(origin, Vec3::new(0.0, -1.0, 0.0)), 0.0).unwrap();
+        let expected = Vec3::new(1.0, 2.0, 0.0).normalize();
+        assert_close(hit.normal.x, expected.x, 1e-3);
+        assert_close(hit.normal.y, expected.y, 1e-3);
+    }
+    
+    #[test]
+    fn rotated_instance_has_tight_bounds() {
+        let cube = Cube::new(Vec3::zero(), 2.0, 0);
+        let rotation = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), std::f32::consts::FRAC_PI_4);
+        let instance = Instance::new(cube, Mat4::from_trs(Vec3::new(0.0, 5.0, 0.0), rotation, Vec3::one()));
+        let (min, max) = instance.get_bounds();
+        assert_close(max.x, 2f32.sqrt(), 1e-5);
+        assert_close(min.z, -(2f32.sqrt()), 1e-5);
+        assert_close(min.y, 4.0, 1e-5);
+        assert_close(max.y, 6.0, 1e-5);
+        // The corner now points along +x
+        let hit = instance.intersect(&Ray::new(Vec3::new(5.0, 5.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)), 0.0).unwrap();
+        assert_close(hit.t, 5.0 - 2f32.sqrt(), 1e-4);
+    }
+    
+    #[test]
+    fn euler_rotated_cube_turns_like_vec3_rotate() {
+        // The +x face, turned the way the Euler builder always turned it
+        let rotation = Vec3::new(0.3, 0.5, 0.7);
+        let cube = Cube::new(Vec3::new(1.0, 2.0, 3.0), 2.0, 0).with_rotation(rotation);
+        let normal = Vec3::new(1.0, 0.0, 0.0).rotate_z(rotation.z).rotate_x(rotation.x).rotate_y(rotation.y);
+        let origin = cube.center + normal * 5.0;
+        let hit = cube.intersect(&Ray::new(origin, -normal), 0.0).unwrap();
+        assert_close(hit.t, 4.0, 1e-4);
+        assert_close(hit.normal.dot(normal), 1.0, 1e-4);
+        assert!(cube.occluded(&Ray::new(origin, -normal), 4.5, 0.0));
+        assert!(!cube.occluded(&Ray::new(origin, -normal), 3.5, 0.0));
+        let (min, max) = cube.get_bounds();
+        assert!(min.x < hit.point.x && hit.point.x < max.x);
+        assert!(min.z < hit.point.z && hit.point.z < max.z);
+    }
+    
+    #[test]
+    fn spinning_cube_pose_matches_any_queried_time() {
+        // Posed at one time, queried at that time and at another
+        let mut posed = SpinningCube::new(Vec3::zero(), 2.0, 0, 0.5);
+        posed.set_time(1.5);
+        let ray = Ray::new(Vec3::new(0.3, 0.2, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        for time in [1.5, 2.5] {
+            let fresh = SpinningCube::new(Vec3::zero(), 2.0, 0, 0.5);
+            let expected = fresh.turn_at(time).intersect(&Cube::new(Vec3::zero(), 2.0, 0), &ray, time).unwrap();
+            let hit = posed.intersect(&ray, time).unwrap();
+            assert_close(hit.t, expected.t, 1e-5);
+            assert_close(hit.normal.x, expected.normal.x, 1e-5);
+        }
+        // At 0.5 rad/s a quarter turn is reached at pi seconds, leaving the cube axis-aligned
+        let hit = posed.intersect(&Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0)), std::f32::consts::PI).unwrap();
+        assert_close(hit.t, 4.0, 1e-4);
+    }
+    
+    #[test]
+    fn shared_mesh_instances_hit_independently() {
+        let triangle: Box<dyn Primitive> = Box::new(Triangle::new(
+            Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0,
+        ));
+        let mesh = Arc::new(Mesh::new(vec![triangle]));
+        let near = Instance::new(mesh.clone(), Mat4::translation(Vec3::new(0.0, 0.0, 2.0)));
+        let far = Instance::new(mesh.clone(), Mat4::translation(Vec3::new(0.0, 0.0, 6.0)));
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0));
+        assert_close(near.intersect(&ray, 0.0).unwrap().t, 2.0, 1e-5);
+        assert_close(far.intersect(&ray, 0.0).unwrap().t, 6.0, 1e-5);
+        assert_eq!(Arc::strong_count(&mesh), 3);
+    }
+}
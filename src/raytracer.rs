@@ -2,11 +2,18 @@ use crate::math::*;
 use crate::materials::*;
 use crate::texture::TextureQuality;
 use crate::primitives::*;
+use crate::light_tree::{LightRef, LightTree};
+use crate::volumetric::VolumetricFog;
+use crate::sky::PreethamSky;
+use crate::clouds::{CloudLayer, CloudStyle};
+use crate::weather::WeatherConditions;
+use crate::particles::{ParticleEmitter, ParticleSystem};
 use rand::Rng;
 
 // Rendering feature toggles
-const ENABLE_AA: bool = false; // Disable per-pixel jitter for higher FPS
+const ENABLE_AA: bool = false; // Disable per-pixel jitter for higher FPS (accumulation jitters on its own)
 
+#[derive(Clone)]
 pub struct Camera {
     pub position: Vec3,
     pub target: Vec3,
@@ -168,6 +175,12 @@ impl CameraFrame {
         let direction = (self.lower_left_corner + self.horizontal * s_jittered + self.vertical * t_jittered - ray_origin).normalize();
         Ray::new(ray_origin, direction)
     }
+    
+    // Ray through a random point of the pixel footprint, for progressive accumulation
+    pub fn get_ray_jittered(&self, x: f32, y: f32) -> Ray {
+        let mut rng = rand::thread_rng();
+        self.get_ray(x + rng.gen::<f32>() - 0.5, y + rng.gen::<f32>() - 0.5)
+    }
 }
 
 fn random_in_unit_disk() -> Vec3 {
@@ -185,13 +198,47 @@ pub struct Light {
     pub color: Vec3,
     pub intensity: f32,
     pub light_type: LightType,
+    pub shadow_samples: u32, // Points sampled on area lights per shading point
 }
 
+// Area lights are centred on `position` and emit from both sides
 #[derive(Clone)]
 pub enum LightType {
     Point,
     Directional(Vec3), // Direction vector
     Spot { direction: Vec3, inner_cone: f32, outer_cone: f32 },
+    Sun { direction: Vec3, angular_diameter: f32 }, // Directional light from a disc in the sky (radians)
+    Rect { half_u: Vec3, half_v: Vec3 },            // Half edge vectors
+    Sphere { radius: f32 },
+    Disk { normal: Vec3, radius: f32 },
+    Box { half_extents: Vec3 },
+}
+
+impl LightType {
+    pub fn is_directional(&self) -> bool {
+        matches!(self, LightType::Directional(_) | LightType::Sun { .. })
+    }
+    
+    // Area that scales an emitter's radiance into light intensity: the full area for flat
+    // shapes (sampling applies their cosine), the mean projected area for solids
+    fn emitting_area(&self) -> f32 {
+        match self {
+            LightType::Rect { half_u, half_v } => 4.0 * half_u.cross(*half_v).length(),
+            LightType::Disk { radius, .. } => std::f32::consts::PI * radius * radius,
+            LightType::Sphere { radius } => std::f32::consts::PI * radius * radius,
+            LightType::Box { half_extents: h } => 2.0 * (h.x * h.y + h.y * h.z + h.z * h.x),
+            _ => 1.0,
+        }
+    }
+}
+
+const DEFAULT_AREA_SHADOW_SAMPLES: u32 = 4;
+
+// One point on a light as seen from a shading point
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f32,
+    pub intensity: f32, // Light intensity after attenuation, spot cone and emitter angle
 }
 
 impl Light {
@@ -201,6 +248,7 @@ impl Light {
             color,
             intensity,
             light_type: LightType::Point,
+            shadow_samples: 1,
         }
     }
     
@@ -210,6 +258,7 @@ impl Light {
             color,
             intensity,
             light_type: LightType::Directional(direction.normalize()),
+            shadow_samples: 1,
         }
     }
     
@@ -223,27 +272,169 @@ impl Light {
                 inner_cone,
                 outer_cone,
             },
+            shadow_samples: 1,
+        }
+    }
+    
+    // `angular_diameter` in degrees; the real sun is about 0.53
+    pub fn sun(direction: Vec3, angular_diameter: f32, color: Vec3, intensity: f32) -> Self {
+        Self {
+            position: Vec3::zero(),
+            color,
+            intensity,
+            light_type: LightType::Sun { direction: direction.normalize(), angular_diameter: angular_diameter.to_radians() },
+            shadow_samples: DEFAULT_AREA_SHADOW_SAMPLES,
+        }
+    }
+    
+    pub fn rect(center: Vec3, half_u: Vec3, half_v: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            position: center,
+            color,
+            intensity,
+            light_type: LightType::Rect { half_u, half_v },
+            shadow_samples: DEFAULT_AREA_SHADOW_SAMPLES,
+        }
+    }
+    
+    pub fn sphere(center: Vec3, radius: f32, color: Vec3, intensity: f32) -> Self {
+        Self {
+            position: center,
+            color,
+            intensity,
+            light_type: LightType::Sphere { radius },
+            shadow_samples: DEFAULT_AREA_SHADOW_SAMPLES,
+        }
+    }
+    
+    pub fn disk(center: Vec3, normal: Vec3, radius: f32, color: Vec3, intensity: f32) -> Self {
+        Self {
+            position: center,
+            color,
+            intensity,
+            light_type: LightType::Disk { normal: normal.normalize(), radius },
+            shadow_samples: DEFAULT_AREA_SHADOW_SAMPLES,
+        }
+    }
+    
+    // Sampled stand-in for an emissive surface; its intensity grows with the emitting area
+    pub fn emitter(position: Vec3, shape: LightType, material: &Material) -> Self {
+        let radiance = material.emissive * shape.emitting_area();
+        let peak = radiance.x.max(radiance.y).max(radiance.z).max(1e-6);
+        Self {
+            position,
+            color: radiance / peak,
+            intensity: peak,
+            light_type: shape,
+            shadow_samples: 1,
+        }
+    }
+    
+    // Relative emitted power, used to pick which lights get shadow rays
+    pub fn power(&self) -> f32 {
+        self.intensity * (0.2126 * self.color.x + 0.7152 * self.color.y + 0.0722 * self.color.z)
+    }
+    
+    // Box around everything that emits (a single point for point and spot lights)
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let extent = match &self.light_type {
+            LightType::Rect { half_u, half_v } => half_u.abs() + half_v.abs(),
+            LightType::Sphere { radius } | LightType::Disk { radius, .. } => Vec3::new(*radius, *radius, *radius),
+            LightType::Box { half_extents } => *half_extents,
+            _ => Vec3::zero(),
+        };
+        (self.position - extent, self.position + extent)
+    }
+    
+    pub fn with_shadow_samples(mut self, samples: u32) -> Self {
+        self.shadow_samples = samples.max(1);
+        self
+    }
+    
+    // Points and plain directional lights have nothing to sample
+    pub fn sample_count(&self) -> u32 {
+        match self.light_type {
+            LightType::Point | LightType::Directional(_) | LightType::Spot { .. } => 1,
+            _ => self.shadow_samples.max(1),
+        }
+    }
+    
+    // Re-aims directional, sun, spot and disk lights; others are unaffected
+    pub fn set_direction(&mut self, new_direction: Vec3) {
+        let new_direction = new_direction.normalize();
+        match &mut self.light_type {
+            LightType::Directional(direction)
+            | LightType::Spot { direction, .. }
+            | LightType::Sun { direction, .. }
+            | LightType::Disk { normal: direction, .. } => *direction = new_direction,
+            _ => {}
         }
     }
     
     pub fn get_light_direction(&self, point: Vec3) -> Vec3 {
         match &self.light_type {
-            LightType::Point => (self.position - point).normalize(),
-            LightType::Directional(dir) => -*dir,
-            LightType::Spot { .. } => (self.position - point).normalize(),
+            LightType::Directional(dir) | LightType::Sun { direction: dir, .. } => -*dir,
+            _ => (self.position - point).normalize(),
         }
     }
     
     pub fn get_attenuation(&self, point: Vec3) -> f32 {
-        match &self.light_type {
-            LightType::Point | LightType::Spot { .. } => {
-                let distance = (self.position - point).length();
-                1.0 / (1.0 + 0.09 * distance + 0.032 * distance * distance)
-            },
-            LightType::Directional(_) => 1.0,
+        if self.light_type.is_directional() {
+            return 1.0;
         }
+        distance_attenuation((self.position - point).length())
     }
     
+    // Picks a random point on the emitter (the centre for points and plain directional lights)
+    pub fn sample(&self, point: Vec3) -> LightSample {
+        let disk = random_in_unit_disk();
+        let (target, emitter_cos) = match &self.light_type {
+            LightType::Directional(dir) => {
+                return LightSample { direction: -*dir, distance: f32::INFINITY, intensity: self.intensity };
+            },
+            LightType::Sun { direction, angular_diameter } => {
+                // Jitter inside the cone the sun disc subtends
+                let (tangent, bitangent) = orthonormal_basis(*direction);
+                let spread = (angular_diameter * 0.5).tan();
+                let jittered = -*direction + (tangent * disk.x + bitangent * disk.y) * spread;
+                return LightSample { direction: jittered.normalize(), distance: f32::INFINITY, intensity: self.intensity };
+            },
+            LightType::Point | LightType::Spot { .. } => (self.position, 1.0),
+            LightType::Rect { half_u, half_v } => {
+                let mut rng = rand::thread_rng();
+                let target = self.position + *half_u * (rng.gen::<f32>() * 2.0 - 1.0) + *half_v * (rng.gen::<f32>() * 2.0 - 1.0);
+                let normal = half_u.cross(*half_v).normalize();
+                (target, normal.dot((target - point).normalize()).abs())
+            },
+            LightType::Sphere { radius } => {
+                // Point on the hemisphere facing the shading point, so the sphere can't shadow it
+                let to_point = point - self.position;
+                if to_point.length() <= *radius {
+                    (self.position, 1.0)
+                } else {
+                    let (tangent, bitangent) = orthonormal_basis(to_point.normalize());
+                    let height = (1.0 - disk.length_squared()).max(0.0).sqrt();
+                    let offset = tangent * disk.x + bitangent * disk.y + to_point.normalize() * height;
+                    (self.position + offset * *radius, 1.0)
+                }
+            },
+            LightType::Disk { normal, radius } => {
+                let (tangent, bitangent) = orthonormal_basis(*normal);
+                let target = self.position + (tangent * disk.x + bitangent * disk.y) * *radius;
+                (target, normal.dot((target - point).normalize()).abs())
+            },
+            LightType::Box { half_extents } => (sample_box_face(self.position, *half_extents, point), 1.0),
+        };
+
+        let offset = target - point;
+        let distance = offset.length();
+        LightSample {
+            direction: offset / distance.max(1e-6),
+            distance,
+            intensity: self.intensity * distance_attenuation(distance) * self.get_spot_factor(point) * emitter_cos,
+        }
+    }
+
     pub fn get_spot_factor(&self, point: Vec3) -> f32 {
         match &self.light_type {
             LightType::Spot { direction, inner_cone, outer_cone } => {
@@ -265,27 +456,130 @@ impl Light {
     }
 }
 
+// Point on a box face turned towards `point`, faces chosen by projected area
+fn sample_box_face(center: Vec3, half: Vec3, point: Vec3) -> Vec3 {
+    let d = point - center;
+    let visible = |d: f32, h: f32, area: f32| if d.abs() > h { area * d.abs() } else { 0.0 };
+    let weights = [
+        visible(d.x, half.x, half.y * half.z),
+        visible(d.y, half.y, half.x * half.z),
+        visible(d.z, half.z, half.x * half.y),
+    ];
+    let total = weights[0] + weights[1] + weights[2];
+    if total <= 0.0 {
+        return center;
+    }
+    
+    let mut rng = rand::thread_rng();
+    let pick = rng.gen::<f32>() * total;
+    let (a, b) = (rng.gen::<f32>() * 2.0 - 1.0, rng.gen::<f32>() * 2.0 - 1.0);
+    if pick < weights[0] {
+        center + Vec3::new(half.x * d.x.signum(), half.y * a, half.z * b)
+    } else if pick < weights[0] + weights[1] {
+        center + Vec3::new(half.x * a, half.y * d.y.signum(), half.z * b)
+    } else {
+        center + Vec3::new(half.x * a, half.y * b, half.z * d.z.signum())
+    }
+}
+
+// Shadow rays each hit spends on positional lights, picked through the light tree
+const LIGHT_SAMPLES: u32 = 2;
+
+pub fn distance_attenuation(distance: f32) -> f32 {
+    1.0 / (1.0 + 0.09 * distance + 0.032 * distance * distance)
+}
+
+// Two unit vectors perpendicular to `n` and to each other
+fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let helper = if n.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let tangent = helper.cross(n).normalize();
+    (tangent, n.cross(tangent))
+}
+
 pub struct Scene {
     pub objects: Vec<Box<dyn Primitive>>,
+    pub materials: MaterialTable,
     pub lights: Vec<Light>,
+    pub emitters: Vec<Light>, // Area lights extracted from emissive geometry
+    light_tree: LightTree,
     pub skybox: Option<Skybox>,
     pub ambient_light: Vec3,
     pub fog: Option<Fog>,
-    pub bvh: Option<BVHNode>,
+    pub volume: Option<VolumetricFog>, // Ray-marched when RenderOptions::volumetric is on
+    pub weather: WeatherConditions, // Precipitation, wet and snowy surfaces
+    pub particles: Vec<ParticleSystem>, // Evaluated per frame by update_particles
+    pub bvh: Option<FlatBvh>,
 }
 
 impl Scene {
     pub fn new() -> Self {
         Scene {
             objects: Vec::new(),
+            materials: MaterialTable::new(),
             lights: Vec::new(),
+            emitters: Vec::new(),
+            light_tree: LightTree::default(),
             skybox: None,
             ambient_light: Vec3::new(0.1, 0.1, 0.15),
             fog: None,
+            volume: None,
+            weather: WeatherConditions::default(),
+            particles: Vec::new(),
             bvh: None,
         }
     }
     
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.add(material)
+    }
+    
+    pub fn material(&self, id: MaterialId) -> &Material {
+        self.materials.get(id)
+    }
+    
+    pub fn add_particles(&mut self, emitter: ParticleEmitter) {
+        self.particles.push(ParticleSystem::new(emitter));
+    }
+    
+    // Moves every particle system to `time`; call once per frame before rendering
+    pub fn update_particles(&mut self, time: f32) {
+        for system in &mut self.particles {
+            system.update(time);
+        }
+    }
+    
+    // Poses animated primitives at `time`; call once per frame before rendering
+    pub fn pose_objects(&mut self, time: f32) {
+        for object in &mut self.objects {
+            object.set_time(time);
+        }
+    }
+    
+    // Registers every emissive primitive as a sampled area light, replacing earlier
+    // extractions. Call again after moving emissive geometry. Returns the emitter count.
+    pub fn extract_emissive_lights(&mut self) -> usize {
+        let mut emitters = Vec::new();
+        for object in &self.objects {
+            object.emitters(&self.materials, &mut emitters);
+        }
+        self.emitters = emitters;
+        self.build_light_tree();
+        self.emitters.len()
+    }
+    
+    // Positional lights are only shaded through the tree: rebuild it after adding,
+    // removing or moving point, spot or area lights (directional ones don't need it)
+    pub fn build_light_tree(&mut self) {
+        self.light_tree = LightTree::build(&self.lights, &self.emitters);
+    }
+    
+    pub fn light(&self, light: LightRef) -> &Light {
+        match light {
+            LightRef::Scene(index) => &self.lights[index],
+            LightRef::Emitter(index) => &self.emitters[index],
+        }
+    }
+    
     pub fn with_ambient_light(mut self, ambient: Vec3) -> Self {
         self.ambient_light = ambient;
         self
@@ -311,26 +605,34 @@ fn union_bounds(a_min: Vec3, a_max: Vec3, b_min: Vec3, b_max: Vec3) -> (Vec3, Ve
     (a_min.min(b_min), a_max.max(b_max))
 }
 
-fn ray_aabb_intersect(ray: &Ray, bmin: Vec3, bmax: Vec3) -> bool {
-    let inv_dir = Vec3::new(
+fn inverse_direction(ray: &Ray) -> Vec3 {
+    Vec3::new(
         if ray.direction.x.abs() < 1e-8 { 1e32 } else { 1.0 / ray.direction.x },
         if ray.direction.y.abs() < 1e-8 { 1e32 } else { 1.0 / ray.direction.y },
         if ray.direction.z.abs() < 1e-8 { 1e32 } else { 1.0 / ray.direction.z },
-    );
-    let mut tmin = (bmin.x - ray.origin.x) * inv_dir.x;
-    let mut tmax = (bmax.x - ray.origin.x) * inv_dir.x;
+    )
+}
+
+// Slab test; returns the distance at which the ray enters the box (0 when it starts
+// inside), or None if it misses or the box is behind the origin
+fn ray_aabb_intersect(origin: Vec3, inv_dir: Vec3, bmin: Vec3, bmax: Vec3) -> Option<f32> {
+    let mut tmin = (bmin.x - origin.x) * inv_dir.x;
+    let mut tmax = (bmax.x - origin.x) * inv_dir.x;
     if tmin > tmax { std::mem::swap(&mut tmin, &mut tmax); }
-    let mut tymin = (bmin.y - ray.origin.y) * inv_dir.y;
-    let mut tymax = (bmax.y - ray.origin.y) * inv_dir.y;
+    let mut tymin = (bmin.y - origin.y) * inv_dir.y;
+    let mut tymax = (bmax.y - origin.y) * inv_dir.y;
     if tymin > tymax { std::mem::swap(&mut tymin, &mut tymax); }
-    if (tmin > tymax) || (tymin > tmax) { return false; }
+    if (tmin > tymax) || (tymin > tmax) { return None; }
     if tymin > tmin { tmin = tymin; }
     if tymax < tmax { tmax = tymax; }
-    let mut tzmin = (bmin.z - ray.origin.z) * inv_dir.z;
-    let mut tzmax = (bmax.z - ray.origin.z) * inv_dir.z;
+    let mut tzmin = (bmin.z - origin.z) * inv_dir.z;
+    let mut tzmax = (bmax.z - origin.z) * inv_dir.z;
     if tzmin > tzmax { std::mem::swap(&mut tzmin, &mut tzmax); }
-    if (tmin > tzmax) || (tzmin > tmax) { return false; }
-    true
+    if (tmin > tzmax) || (tzmin > tmax) { return None; }
+    if tzmin > tmin { tmin = tzmin; }
+    if tzmax < tmax { tmax = tzmax; }
+    if tmax < 0.0 { return None; }
+    Some(tmin.max(0.0))
 }
 
 struct ObjectInfo {
@@ -340,20 +642,58 @@ struct ObjectInfo {
     centroid: Vec3,
 }
 
-pub fn build_scene_bvh(scene: &mut Scene) {
-    if scene.objects.is_empty() { return; }
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BvhBuilder {
+    Median, // Sort on the longest axis and split in half
+    Sah,    // Binned surface area heuristic
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BvhConfig {
+    pub builder: BvhBuilder,
+    pub max_leaf_size: usize,
+    pub bins: usize, // SAH candidate planes per axis, plus one
+}
+
+impl Default for BvhConfig {
+    fn default() -> Self {
+        Self { builder: BvhBuilder::Sah, max_leaf_size: 4, bins: 16 }
+    }
+}
+
+impl BvhConfig {
+    // The original builder: median splits with 8-object leaves
+    pub fn median() -> Self {
+        Self { builder: BvhBuilder::Median, max_leaf_size: 8, bins: 0 }
+    }
+}
+
+// Relative costs used both by the SAH builder and by BvhStats
+const SAH_TRAVERSAL_COST: f32 = 1.0;
+const SAH_INTERSECT_COST: f32 = 1.0;
+
+fn surface_area(bmin: Vec3, bmax: Vec3) -> f32 {
+    let d = (bmax - bmin).max(Vec3::zero());
+    2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+fn axis_value(v: Vec3, axis: usize) -> f32 {
+    match axis { 0 => v.x, 1 => v.y, _ => v.z }
+}
+
+pub fn build_bvh(objects: &[Box<dyn Primitive>], config: &BvhConfig) -> Option<BVHNode> {
+    if objects.is_empty() { return None; }
     // Gather bounds
-    let mut infos: Vec<ObjectInfo> = Vec::with_capacity(scene.objects.len());
-    for (i, obj) in scene.objects.iter().enumerate() {
+    let mut infos: Vec<ObjectInfo> = Vec::with_capacity(objects.len());
+    for (i, obj) in objects.iter().enumerate() {
         let (bmin, bmax) = obj.get_bounds();
         let centroid = (bmin + bmax) * 0.5;
         infos.push(ObjectInfo { index: i, bmin, bmax, centroid });
     }
-    let node = build_bvh_recursive(&mut infos[..]);
-    scene.bvh = Some(node);
+    Some(build_bvh_recursive(&mut infos[..], config, 1))
 }
 
-fn build_bvh_recursive(objects: &mut [ObjectInfo]) -> BVHNode {
+fn build_bvh_recursive(objects: &mut [ObjectInfo], config: &BvhConfig, depth: usize) -> BVHNode {
     // Compute bounds of all
     let mut bounds_min = Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
     let mut bounds_max = Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
@@ -361,27 +701,28 @@ fn build_bvh_recursive(objects: &mut [ObjectInfo]) -> BVHNode {
         let (bmin, bmax) = union_bounds(bounds_min, bounds_max, info.bmin, info.bmax);
         bounds_min = bmin; bounds_max = bmax;
     }
-    if objects.len() <= 8 {
-        return BVHNode {
+    let max_leaf_size = config.max_leaf_size.max(1);
+    let mid = match config.builder {
+        // Degenerate inputs can make SAH peel off one object per level; past the depth
+        // the traversal stack can hold, whatever is left goes into one leaf
+        _ if depth >= BVH_MAX_DEPTH => None,
+        BvhBuilder::Median if objects.len() > max_leaf_size => Some(median_split(objects, bounds_min, bounds_max)),
+        BvhBuilder::Sah => sah_split(objects, bounds_min, bounds_max, config),
+        _ => None,
+    };
+    let mid = match mid {
+        Some(mid) => mid,
+        None => return BVHNode {
             bounds_min,
             bounds_max,
             left: None,
             right: None,
             indices: objects.iter().map(|o| o.index).collect(),
-        };
-    }
-    // Choose split axis by largest extent
-    let extent = bounds_max - bounds_min;
-    let axis = if extent.x > extent.y && extent.x > extent.z { 0 } else if extent.y > extent.z { 1 } else { 2 };
-    objects.sort_by(|a, b| {
-        let ca = match axis { 0 => a.centroid.x, 1 => a.centroid.y, _ => a.centroid.z };
-        let cb = match axis { 0 => b.centroid.x, 1 => b.centroid.y, _ => b.centroid.z };
-        ca.partial_cmp(&cb).unwrap_or(std::cmp::Ordering::Equal)
-    });
-    let mid = objects.len() / 2;
+        },
+    };
     let (left_slice, right_slice) = objects.split_at_mut(mid);
-    let left = build_bvh_recursive(left_slice);
-    let right = build_bvh_recursive(right_slice);
+    let left = build_bvh_recursive(left_slice, config, depth + 1);
+    let right = build_bvh_recursive(right_slice, config, depth + 1);
     BVHNode {
         bounds_min,
         bounds_max,
@@ -391,37 +732,292 @@ fn build_bvh_recursive(objects: &mut [ObjectInfo]) -> BVHNode {
     }
 }
 
-fn intersect_bvh(ray: &Ray, node: &BVHNode, scene: &Scene, time: f32) -> Option<HitInfo> {
-    if !ray_aabb_intersect(ray, node.bounds_min, node.bounds_max) {
-        return None;
+fn median_split(objects: &mut [ObjectInfo], bounds_min: Vec3, bounds_max: Vec3) -> usize {
+    // Choose split axis by largest extent
+    let extent = bounds_max - bounds_min;
+    let axis = if extent.x > extent.y && extent.x > extent.z { 0 } else if extent.y > extent.z { 1 } else { 2 };
+    objects.sort_by(|a, b| {
+        let ca = axis_value(a.centroid, axis);
+        let cb = axis_value(b.centroid, axis);
+        ca.partial_cmp(&cb).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    objects.len() / 2
+}
+
+// Buckets centroids into `config.bins` slabs per axis and picks the cheapest plane
+// between buckets. Returns None when keeping a leaf is cheaper (and allowed).
+fn sah_split(objects: &mut [ObjectInfo], bounds_min: Vec3, bounds_max: Vec3, config: &BvhConfig) -> Option<usize> {
+    let n = objects.len();
+    if n <= 1 { return None; }
+    let bins = config.bins.max(2);
+    let leaf_cost = SAH_INTERSECT_COST * n as f32;
+    let parent_area = surface_area(bounds_min, bounds_max);
+
+    let mut centroid_min = Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut centroid_max = Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for info in objects.iter() {
+        centroid_min = centroid_min.min(info.centroid);
+        centroid_max = centroid_max.max(info.centroid);
+    }
+
+    // (axis, last bin on the left side, cost)
+    let mut best: Option<(usize, usize, f32)> = None;
+    for axis in 0..3 {
+        let lo = axis_value(centroid_min, axis);
+        let extent = axis_value(centroid_max, axis) - lo;
+        if extent <= 1e-6 { continue; }
+
+        let mut counts = vec![0usize; bins];
+        let mut bin_bounds = vec![(Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY), Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)); bins];
+        for info in objects.iter() {
+            let b = bin_index(info.centroid, axis, lo, extent, bins);
+            counts[b] += 1;
+            bin_bounds[b] = union_bounds(bin_bounds[b].0, bin_bounds[b].1, info.bmin, info.bmax);
+        }
+
+        // Sweep from the right so each candidate plane is priced in O(1)
+        let mut right_area = vec![0.0f32; bins];
+        let mut right_count = vec![0usize; bins];
+        let mut acc = bin_bounds[bins - 1];
+        let mut count = 0;
+        for b in (1..bins).rev() {
+            acc = union_bounds(acc.0, acc.1, bin_bounds[b].0, bin_bounds[b].1);
+            count += counts[b];
+            right_area[b] = surface_area(acc.0, acc.1);
+            right_count[b] = count;
+        }
+        let mut acc = bin_bounds[0];
+        let mut count = 0;
+        for b in 0..bins - 1 {
+            acc = union_bounds(acc.0, acc.1, bin_bounds[b].0, bin_bounds[b].1);
+            count += counts[b];
+            if count == 0 || right_count[b + 1] == 0 { continue; }
+            let cost = SAH_TRAVERSAL_COST
+                + SAH_INTERSECT_COST * (surface_area(acc.0, acc.1) * count as f32 + right_area[b + 1] * right_count[b + 1] as f32) / parent_area.max(1e-12);
+            if best.is_none_or(|(_, _, c)| cost < c) {
+                best = Some((axis, b, cost));
+            }
+        }
     }
-    let mut best_hit: Option<HitInfo> = None;
-    let mut best_t = f32::INFINITY;
-    if node.left.is_none() && node.right.is_none() {
-        for &idx in &node.indices {
-            if let Some(hit) = scene.objects[idx].intersect(ray, time) {
-                if hit.t > 0.001 && hit.t < best_t {
-                    best_t = hit.t;
-                    best_hit = Some(hit);
+
+    match best {
+        Some((_, _, cost)) if n <= config.max_leaf_size.max(1) && leaf_cost <= cost => None,
+        Some((axis, split_bin, _)) => {
+            let lo = axis_value(centroid_min, axis);
+            let extent = axis_value(centroid_max, axis) - lo;
+            // In-place partition by bin
+            let mut mid = 0;
+            for i in 0..n {
+                if bin_index(objects[i].centroid, axis, lo, extent, bins) <= split_bin {
+                    objects.swap(i, mid);
+                    mid += 1;
                 }
             }
+            Some(mid)
+        },
+        // All centroids coincide: no plane separates them, so fall back to halving
+        None if n > config.max_leaf_size.max(1) => Some(median_split(objects, bounds_min, bounds_max)),
+        None => None,
+    }
+}
+
+fn bin_index(centroid: Vec3, axis: usize, lo: f32, extent: f32, bins: usize) -> usize {
+    let b = ((axis_value(centroid, axis) - lo) / extent * bins as f32) as usize;
+    b.min(bins - 1)
+}
+
+// Quality figures for comparing builders; `sah_cost` is the expected cost of a random
+// ray that hits the root, in units of one primitive intersection
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub max_depth: usize,
+    pub max_leaf_size: usize,
+    pub primitives: usize,
+    pub sah_cost: f32,
+}
+
+impl BVHNode {
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats::default();
+        let root_area = surface_area(self.bounds_min, self.bounds_max).max(1e-12);
+        self.accumulate_stats(&mut stats, 1, root_area);
+        stats
+    }
+
+    fn accumulate_stats(&self, stats: &mut BvhStats, depth: usize, root_area: f32) {
+        stats.nodes += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        let area_ratio = surface_area(self.bounds_min, self.bounds_max) / root_area;
+        match (&self.left, &self.right) {
+            (None, None) => {
+                stats.leaves += 1;
+                stats.primitives += self.indices.len();
+                stats.max_leaf_size = stats.max_leaf_size.max(self.indices.len());
+                stats.sah_cost += area_ratio * SAH_INTERSECT_COST * self.indices.len() as f32;
+            },
+            (left, right) => {
+                stats.sah_cost += area_ratio * SAH_TRAVERSAL_COST;
+                for child in [left, right].into_iter().flatten() {
+                    child.accumulate_stats(stats, depth + 1, root_area);
+                }
+            },
         }
-        return best_hit;
     }
-    if let Some(left) = &node.left {
-        if let Some(hit) = intersect_bvh(ray, left, scene, time) {
-            best_t = hit.t;
-            best_hit = Some(hit);
+}
+
+impl std::fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} nodes ({} leaves), depth {}, {} primitives (max {} per leaf), SAH cost {:.2}",
+            self.nodes, self.leaves, self.max_depth, self.primitives, self.max_leaf_size, self.sah_cost
+        )
+    }
+}
+
+// Linearised BVH: nodes in depth-first order, so an interior node's first child is
+// the next node and only the second child's index needs storing (32 bytes per node)
+#[derive(Clone, Copy)]
+pub struct FlatBvhNode {
+    pub bounds_min: Vec3,
+    pub bounds_max: Vec3,
+    pub offset: u32, // Leaf: first slot in FlatBvh.indices; interior: second child
+    pub count: u32,  // Primitives in a leaf, 0 for interior nodes
+}
+
+#[derive(Clone)]
+pub struct FlatBvh {
+    pub nodes: Vec<FlatBvhNode>,
+    pub indices: Vec<usize>, // Leaf ranges into Scene.objects
+}
+
+// Traversal keeps at most one entry per level, plus one; the builders stop splitting
+// at BVH_MAX_DEPTH so their trees always fit
+const BVH_STACK_SIZE: usize = 64;
+const BVH_MAX_DEPTH: usize = BVH_STACK_SIZE - 1;
+
+impl FlatBvh {
+    pub fn from_tree(root: &BVHNode) -> Self {
+        let mut bvh = FlatBvh { nodes: Vec::new(), indices: Vec::new() };
+        let depth = bvh.flatten(root, 1);
+        debug_assert!(depth <= BVH_MAX_DEPTH, "BVH depth {} exceeds the traversal stack", depth);
+        bvh
+    }
+
+    // Returns the depth of the subtree
+    fn flatten(&mut self, node: &BVHNode, depth: usize) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(FlatBvhNode { bounds_min: node.bounds_min, bounds_max: node.bounds_max, offset: 0, count: 0 });
+        match (&node.left, &node.right) {
+            (Some(left), Some(right)) => {
+                let left_depth = self.flatten(left, depth + 1);
+                self.nodes[index].offset = self.nodes.len() as u32;
+                let right_depth = self.flatten(right, depth + 1);
+                left_depth.max(right_depth)
+            },
+            // The builders never leave a single child; splice it in place of its parent
+            (Some(child), None) | (None, Some(child)) => {
+                self.nodes.pop();
+                self.flatten(child, depth)
+            },
+            (None, None) => {
+                self.nodes[index].offset = self.indices.len() as u32;
+                self.nodes[index].count = node.indices.len() as u32;
+                self.indices.extend_from_slice(&node.indices);
+                depth
+            },
+        }
+    }
+
+    // Closest hit; visits the nearer child first and skips nodes that start beyond
+    // the best hit found so far
+    pub fn intersect(&self, ray: &Ray, objects: &[Box<dyn Primitive>], time: f32) -> Option<HitInfo> {
+        let inv_dir = inverse_direction(ray);
+        let mut best_hit: Option<HitInfo> = None;
+        let mut best_t = f32::INFINITY;
+
+        let root = self.nodes.first()?;
+        let mut stack = [(0u32, 0.0f32); BVH_STACK_SIZE];
+        let mut stack_len = 0;
+        if let Some(entry) = ray_aabb_intersect(ray.origin, inv_dir, root.bounds_min, root.bounds_max) {
+            stack[0] = (0, entry);
+            stack_len = 1;
+        }
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let (index, entry) = stack[stack_len];
+            if entry > best_t { continue; }
+            let node = &self.nodes[index as usize];
+
+            if node.count > 0 {
+                let first = node.offset as usize;
+                for &idx in &self.indices[first..first + node.count as usize] {
+                    if let Some(hit) = objects[idx].intersect(ray, time) {
+                        if hit.t > 0.001 && hit.t < best_t {
+                            best_t = hit.t;
+                            best_hit = Some(hit);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let (left, right) = (index + 1, node.offset);
+            let hit_left = self.child_entry(left, ray.origin, inv_dir, best_t);
+            let hit_right = self.child_entry(right, ray.origin, inv_dir, best_t);
+            // Push the farther child first so the nearer one is popped next
+            match (hit_left, hit_right) {
+                (Some(tl), Some(tr)) => {
+                    let (near, far) = if tl <= tr { ((left, tl), (right, tr)) } else { ((right, tr), (left, tl)) };
+                    stack[stack_len] = far;
+                    stack[stack_len + 1] = near;
+                    stack_len += 2;
+                },
+                (Some(tl), None) => { stack[stack_len] = (left, tl); stack_len += 1; },
+                (None, Some(tr)) => { stack[stack_len] = (right, tr); stack_len += 1; },
+                (None, None) => {},
+            }
         }
+        best_hit
     }
-    if let Some(right) = &node.right {
-        if let Some(hit) = intersect_bvh(ray, right, scene, time) {
-            if hit.t < best_t {
-                best_hit = Some(hit);
+
+    // Any-hit query: stops at the first primitive that blocks the ray before max_t
+    pub fn occluded(&self, ray: &Ray, max_t: f32, objects: &[Box<dyn Primitive>], time: f32) -> bool {
+        let inv_dir = inverse_direction(ray);
+        let mut stack = [0u32; BVH_STACK_SIZE];
+        let mut stack_len = 0;
+        if self.child_entry(0, ray.origin, inv_dir, max_t).is_some() {
+            stack_len = 1;
+        }
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let index = stack[stack_len];
+            let node = &self.nodes[index as usize];
+            if node.count > 0 {
+                let first = node.offset as usize;
+                if self.indices[first..first + node.count as usize].iter().any(|&idx| objects[idx].occluded(ray, max_t, time)) {
+                    return true;
+                }
+                continue;
+            }
+            for child in [index + 1, node.offset] {
+                if self.child_entry(child, ray.origin, inv_dir, max_t).is_some() {
+                    stack[stack_len] = child;
+                    stack_len += 1;
+                }
             }
         }
+        false
+    }
+
+    fn child_entry(&self, index: u32, origin: Vec3, inv_dir: Vec3, best_t: f32) -> Option<f32> {
+        let node = &self.nodes[index as usize];
+        ray_aabb_intersect(origin, inv_dir, node.bounds_min, node.bounds_max).filter(|&t| t <= best_t)
     }
-    best_hit
 }
 
 pub struct Fog {
@@ -472,6 +1068,27 @@ pub struct Skybox {
     pub sun_direction: Vec3,
     pub sun_size: f32,
     pub time_of_day: f32, // 0.0 = night, 1.0 = day
+    pub sky: Option<PreethamSky>, // Physical daylight replacing the day colours
+    pub moon_phase: f32, // 0 = full, 0.5 = new; one of eight phases per in-game day
+    pub moon_size: f32,
+    pub clouds: Option<CloudLayer>,
+    pub overcast: f32, // Weather greying the sky, 0..1
+    pub flash: f32,    // Lightning lighting up the sky
+}
+
+// Radiance of the sun disc before the atmosphere tints it
+const SUN_DISC_RADIANCE: f32 = 20.0;
+const MOON_DISC_RADIANCE: f32 = 1.5;
+const MOON_PHASES: f32 = 8.0;
+// Sun and moon radiance lighting the clouds, relative to their light colour
+const CLOUD_SUN_RADIANCE: f32 = 6.0;
+const CLOUD_MOON_RADIANCE: f32 = 2.0;
+// Brightness of the sky at the peak of a lightning flash
+const FLASH_RADIANCE: f32 = 1.5;
+
+// Cool tint of the moon disc and of moonlight
+fn moon_color() -> Vec3 {
+    Vec3::new(0.75, 0.82, 1.0)
 }
 
 impl Skybox {
@@ -485,6 +1102,12 @@ impl Skybox {
             sun_direction: Vec3::new(0.3, 0.6, 0.7).normalize(),
             sun_size: 0.04,
             time_of_day: 1.0,
+            sky: None,
+            moon_phase: 0.0,
+            moon_size: 0.001,
+            clouds: None,
+            overcast: 0.0,
+            flash: 0.0,
         }
     }
     
@@ -498,6 +1121,47 @@ impl Skybox {
             sun_direction: Vec3::new(0.3, 0.6, 0.7).normalize(),
             sun_size: 0.04,
             time_of_day: 1.0,
+            sky: None,
+            moon_phase: 0.0,
+            moon_size: 0.001,
+            clouds: None,
+            overcast: 0.0,
+            flash: 0.0,
+        }
+    }
+    
+    // Preetham daylight for the given turbidity, with the night gradient and stars after
+    // sunset. `sun_angular_diameter` is in degrees.
+    pub fn physical(turbidity: f32, sun_angular_diameter: f32, night_top: Vec3, night_horizon: Vec3) -> Self {
+        let sun_direction = Vec3::new(0.3, 0.6, 0.7).normalize();
+        let sky = PreethamSky::new(sun_direction, turbidity);
+        Skybox {
+            top_color: sky.radiance(Vec3::new(0.0, 1.0, 0.0)),
+            horizon_color: sky.radiance(Vec3::new(-sun_direction.x, 0.0, -sun_direction.z)),
+            night_top_color: night_top,
+            night_horizon_color: night_horizon,
+            sun_color: sky.sun_transmittance(),
+            sun_direction,
+            sun_size: 1.0 - (sun_angular_diameter * 0.5).to_radians().cos(),
+            time_of_day: 1.0,
+            sky: Some(sky),
+            moon_phase: 0.0,
+            moon_size: 0.001,
+            clouds: None,
+            overcast: 0.0,
+            flash: 0.0,
+        }
+    }
+    
+    pub fn with_clouds(mut self, clouds: CloudLayer) -> Self {
+        self.clouds = Some(clouds);
+        self
+    }
+    
+    // Switch to the default layer of `style`, or clear the sky; drift resumes on the next time update
+    pub fn set_cloud_style(&mut self, style: Option<CloudStyle>) {
+        if self.clouds.as_ref().map(|clouds| clouds.style) != style {
+            self.clouds = style.map(CloudLayer::new);
         }
     }
     
@@ -508,10 +1172,98 @@ impl Skybox {
         // Update sun direction based on time
         let sun_angle = time * speed;
         self.sun_direction = Vec3::new(0.3, sun_angle.sin(), sun_angle.cos()).normalize();
+        if let Some(sky) = &mut self.sky {
+            *sky = PreethamSky::new(self.sun_direction, sky.turbidity);
+            self.sun_color = sky.sun_transmittance();
+        }
+        
+        // A new phase each full turn of the sun, starting from a full moon
+        let day = (sun_angle / std::f32::consts::TAU).floor();
+        self.moon_phase = day.rem_euclid(MOON_PHASES) / MOON_PHASES;
+        
+        if let Some(clouds) = &mut self.clouds {
+            clouds.set_time(time);
+        }
+    }
+    
+    // The moon sits opposite the sun, as in Minecraft
+    pub fn moon_direction(&self) -> Vec3 {
+        -self.sun_direction
+    }
+    
+    // Lit fraction of the moon disc for the current phase
+    pub fn moon_illumination(&self) -> f32 {
+        0.5 + 0.5 * (self.moon_phase * std::f32::consts::TAU).cos()
+    }
+    
+    // Colour and strength (0..1) for the directional moon light: brightest at full moon,
+    // and only once the sun has set
+    pub fn moon_light(&self) -> (Vec3, f32) {
+        (moon_color(), (1.0 - self.daylight()) * self.moon_illumination())
+    }
+    
+    // 1 with the sun up, fading to 0 as it sinks just below the horizon
+    pub fn daylight(&self) -> f32 {
+        Vec3::smoothstep(-0.1, 0.05, self.sun_direction.y)
+    }
+    
+    // Colour and strength (0..1) for the directional sun light, from the sky model
+    pub fn sun_light(&self) -> Option<(Vec3, f32)> {
+        self.sky.as_ref().map(|sky| (sky.sun_transmittance(), self.daylight()))
+    }
+    
+    // Storm clouds thicken and grey the sky; `flash` is the current lightning strength
+    pub fn set_weather(&mut self, overcast: f32, flash: f32) {
+        self.overcast = overcast.clamp(0.0, 1.0);
+        self.flash = flash.max(0.0);
+        if let Some(clouds) = &mut self.clouds {
+            clouds.set_overcast(self.overcast);
+        }
+    }
+    
+    // Fraction of a directional light from `light_dir` that the clouds let through to `point`
+    pub fn cloud_shadow(&self, point: Vec3, light_dir: Vec3) -> f32 {
+        match &self.clouds {
+            Some(clouds) if clouds.cast_shadows => clouds.transmittance(point, light_dir),
+            _ => 1.0,
+        }
     }
     
     pub fn color_at(&self, direction: Vec3) -> Vec3 {
         let dir = direction.normalize();
+        let sky_color = self.sky_color_at(dir);
+        let color = match &self.clouds {
+            Some(clouds) => {
+                // Clouds are lit by the sun by day and the moon by night, and by the sky above them
+                let (light_dir, light) = if self.sun_direction.y > 0.0 {
+                    (self.sun_direction, self.sun_color * (CLOUD_SUN_RADIANCE * self.daylight()))
+                } else {
+                    let (color, strength) = self.moon_light();
+                    (self.moon_direction(), color * (CLOUD_MOON_RADIANCE * strength))
+                };
+                let dimming = 1.0 - 0.7 * self.overcast;
+                let ambient = self.sky_color_at(Vec3::new(0.0, 1.0, 0.0));
+                let (scattered, transmittance) = clouds.march(dir, light_dir, light * dimming, ambient * dimming);
+                sky_color * transmittance + scattered
+            },
+            None => sky_color,
+        };
+        self.weathered(color)
+    }
+    
+    // Overcast skies lose their colour and half their brightness; lightning flashes add to them
+    fn weathered(&self, color: Vec3) -> Vec3 {
+        if self.overcast <= 0.0 && self.flash <= 0.0 {
+            return color;
+        }
+        let grey = Vec3::one() * ((color.x + color.y + color.z) / 3.0 * 0.5);
+        color.lerp(grey, self.overcast) + Vec3::new(0.8, 0.85, 1.0) * (self.flash * FLASH_RADIANCE)
+    }
+    
+    fn sky_color_at(&self, dir: Vec3) -> Vec3 {
+        if let Some(sky) = &self.sky {
+            return self.physical_color_at(sky, dir);
+        }
         
         // Sky gradient based on height
         let t = (dir.y * 0.5 + 0.5).clamp(0.0, 1.0);
@@ -538,45 +1290,104 @@ impl Skybox {
             } else {
                 // Night stars
                 if self.time_of_day < 0.4 {
-                    // hash-based star field
-                    let h = (dir.x * 157.0 + dir.y * 311.0 + dir.z * 653.0).sin().abs();
-                    let star = if h > 0.995 { (h - 0.995) * 200.0 } else { 0.0 };
-                    let star_color = Vec3::new(1.0, 1.0, 1.0) * star;
-                    sky_color + star_color * (0.6 - self.time_of_day)
+                    sky_color + self.night_sky_at(dir) * (0.6 - self.time_of_day)
                 } else {
                     sky_color
                 }
             }
         }
     }
+    
+    fn physical_color_at(&self, sky: &PreethamSky, dir: Vec3) -> Vec3 {
+        let daylight = self.daylight();
+        let t = (dir.y * 0.5 + 0.5).clamp(0.0, 1.0);
+        let night = self.night_horizon_color.lerp(self.night_top_color, t) + self.night_sky_at(dir) * 0.6;
+        let mut color = night.lerp(sky.radiance(dir), daylight);
+        
+        // Sun disc in the colour that reaches the ground
+        if dir.dot(self.sun_direction) > 1.0 - self.sun_size {
+            color = color + self.sun_color * (SUN_DISC_RADIANCE * daylight);
+        }
+        color
+    }
+    
+    // Moon disc where `dir` falls on it (its dark side hides the stars), stars elsewhere
+    fn night_sky_at(&self, dir: Vec3) -> Vec3 {
+        let moon = self.moon_direction();
+        if dir.dot(moon) <= 1.0 - self.moon_size {
+            return Vec3::one() * star_field(dir);
+        }
+        
+        // Shade the disc as a sphere lit from the side the phase puts the sun on
+        let (tangent, bitangent) = orthonormal_basis(moon);
+        let radius = (1.0 - (1.0 - self.moon_size).powi(2)).sqrt();
+        let (u, v) = (dir.dot(tangent) / radius, dir.dot(bitangent) / radius);
+        let normal = Vec3::new(u, v, (1.0 - u * u - v * v).max(0.0).sqrt());
+        let angle = self.moon_phase * std::f32::consts::TAU;
+        let lit = Vec3::smoothstep(-0.05, 0.1, normal.dot(Vec3::new(angle.sin(), 0.0, angle.cos())));
+        moon_color() * (MOON_DISC_RADIANCE * (0.03 + 0.97 * lit))
+    }
+}
+
+// Hash-based star field: brightness of the star (if any) in direction `dir`
+fn star_field(dir: Vec3) -> f32 {
+    let h = (dir.x * 157.0 + dir.y * 311.0 + dir.z * 653.0).sin().abs();
+    if h > 0.995 { (h - 0.995) * 200.0 } else { 0.0 }
 }
 
 pub struct HitInfo {
     pub t: f32,
     pub point: Vec3,
     pub normal: Vec3,
-    pub material: Material,
+    pub material: MaterialId,
     pub uv: (f32, f32),
 }
 
 // Enhanced raytracing parameters (upper bound). Actual depth is controlled by RenderOptions.max_depth
 const MAX_DEPTH: i32 = 6;
-const SAMPLES_PER_PIXEL: i32 = 1;
+pub const SAMPLES_PER_PIXEL: u32 = 1; // Jittered samples each accumulation pass adds per pixel
 const RUSSIAN_ROULETTE_DEPTH: i32 = 3;
 const MIN_CONTRIBUTION: f32 = 0.001;
+const SHADOW_EPSILON: f32 = 0.01; // Shadow rays stop this short of sampled light surfaces
+const MAX_SHADOW_CROSSINGS: u32 = 8; // Transparent surfaces a shadow ray may pass before giving up
+// Weathered surfaces: how far up a surface looks for cover, and how rain and snow change it
+const SKY_EXPOSURE_DISTANCE: f32 = 64.0;
+const WET_DARKENING: f32 = 0.45;
+const WET_ALPHA: f32 = 0.01;
+const SNOW_ALPHA: f32 = 0.36;
+const WATER_IOR: f32 = 1.33;
+const PUDDLE_REFLECTANCE: f32 = 0.6;
 
 // Runtime render options to trade quality for performance
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum ShadowMode { None, SunOnly, Full }
 
-#[derive(Clone, Copy)]
+// Whitted: ambient + direct light with mirror reflection/refraction.
+// PathTraced: Monte Carlo global illumination, meant to be accumulated over many samples.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Integrator { Whitted, PathTraced }
+
+#[derive(Clone, Copy, PartialEq)]
 pub struct RenderOptions {
     pub shadow_mode: ShadowMode,
     pub max_depth: i32,
     pub far_simplify_distance: f32,
+    pub integrator: Integrator,
+    pub volumetric: bool, // March Scene::volume (light shafts, haze around lights)
 }
 
 pub fn trace_ray(ray: &Ray, scene: &Scene, depth: i32, time: f32, rotation_y: f32, opts: &RenderOptions) -> Vec3 {
+    trace_ray_in(ray, scene, depth, time, rotation_y, opts, None)
+}
+
+// `medium` is the material the ray travels through before its next hit (None for air);
+// whatever the ray finds is attenuated by that material's absorption over the distance
+#[allow(clippy::too_many_arguments)]
+fn trace_ray_in(ray: &Ray, scene: &Scene, depth: i32, time: f32, rotation_y: f32, opts: &RenderOptions, medium: Option<&Material>) -> Vec3 {
+    if opts.integrator == Integrator::PathTraced {
+        return trace_path(ray, scene, depth, time, rotation_y, opts, true, medium);
+    }
+    
     // Russian roulette termination
     // Hard stop by user options
     if depth >= opts.max_depth { return Vec3::zero(); }
@@ -594,29 +1405,330 @@ pub fn trace_ray(ray: &Ray, scene: &Scene, depth: i32, time: f32, rotation_y: f3
     
     // Find closest intersection
     if let Some(hit) = intersect_scene(&rotated_ray, scene, time) {
-        let color = shade_hit(ray, &hit, scene, depth, time, rotation_y, opts);
+        let mut color = shade_hit(ray, &hit, scene, depth, time, rotation_y, opts);
+        if let Some(medium) = medium {
+            color = color * medium.transmittance(hit.t);
+        }
         
         // Apply fog if present
         if let Some(fog) = &scene.fog {
-            fog.apply(color, hit.t)
-        } else {
-            color
+            color = fog.apply(color, hit.t);
         }
+        let color = through_volume(&rotated_ray, hit.t, color, scene, depth, time, opts, medium.is_some());
+        through_precipitation(&rotated_ray, hit.t, color, scene, depth, time)
     } else {
-        // Background/skybox
-        if let Some(skybox) = &scene.skybox {
-            skybox.color_at(ray.direction)
-        } else {
-            // Default gradient sky
-            let t = 0.5 * (ray.direction.normalize().y + 1.0);
-            Vec3::new(0.5, 0.7, 1.0).lerp(Vec3::new(1.0, 1.0, 1.0), t)
+        let color = through_volume(&rotated_ray, f32::INFINITY, background(ray, scene), scene, depth, time, opts, medium.is_some());
+        through_precipitation(&rotated_ray, f32::INFINITY, color, scene, depth, time)
+    }
+}
+
+fn background(ray: &Ray, scene: &Scene) -> Vec3 {
+    if let Some(skybox) = &scene.skybox {
+        skybox.color_at(ray.direction)
+    } else {
+        // Default gradient sky
+        let t = 0.5 * (ray.direction.normalize().y + 1.0);
+        Vec3::new(0.5, 0.7, 1.0).lerp(Vec3::new(1.0, 1.0, 1.0), t)
+    }
+}
+
+// One Monte Carlo sample of incoming radiance along `ray`. Each hit adds its emission and
+// direct light from `scene.lights`, then follows a single bounce chosen by the material:
+// mirror reflection, refraction, or a cosine-weighted diffuse direction. Indirect light
+// replaces the flat `ambient_light` term. `emission` is false after diffuse bounces when
+// extracted emitters already contributed that light through direct sampling.
+#[allow(clippy::too_many_arguments)]
+fn trace_path(ray: &Ray, scene: &Scene, depth: i32, time: f32, rotation_y: f32, opts: &RenderOptions, emission: bool, medium: Option<&Material>) -> Vec3 {
+    if depth >= opts.max_depth { return Vec3::zero(); }
+    
+    let rotated_ray = Ray::new(ray.origin.rotate_y(-rotation_y), ray.direction.rotate_y(-rotation_y));
+    let hit = match intersect_scene(&rotated_ray, scene, time) {
+        Some(hit) => hit,
+        None => {
+            let color = through_volume(&rotated_ray, f32::INFINITY, background(ray, scene), scene, depth, time, opts, medium.is_some());
+            return through_precipitation(&rotated_ray, f32::INFINITY, color, scene, depth, time);
+        },
+    };
+    
+    let mut color = shade_path(ray, &hit, scene, depth, time, rotation_y, opts, emission);
+    if let Some(medium) = medium {
+        color = color * medium.transmittance(hit.t);
+    }
+    if let Some(fog) = &scene.fog {
+        color = fog.apply(color, hit.t);
+    }
+    let color = through_volume(&rotated_ray, hit.t, color, scene, depth, time, opts, medium.is_some());
+    through_precipitation(&rotated_ray, hit.t, color, scene, depth, time)
+}
+
+// Radiance `color` arriving from `distance` along `ray` after crossing the scene's
+// participating medium. Camera rays march it with single scattering; secondary rays only
+// use its transmittance and ambient in-scattering. Rays inside water or glass skip it.
+#[allow(clippy::too_many_arguments)]
+fn through_volume(ray: &Ray, distance: f32, color: Vec3, scene: &Scene, depth: i32, time: f32, opts: &RenderOptions, in_medium: bool) -> Vec3 {
+    let volume = match &scene.volume {
+        Some(volume) if opts.volumetric && !in_medium => volume,
+        _ => return color,
+    };
+    let distance = distance.min(volume.max_distance);
+    if depth > 0 {
+        let transmittance = volume.transmittance(ray, distance);
+        return color * transmittance + scene.ambient_light * volume.color * (1.0 - transmittance);
+    }
+    
+    // Jittered start so the step pattern averages out under accumulation
+    let step = distance / volume.steps as f32;
+    let jitter = rand::random::<f32>();
+    let mut transmittance = 1.0;
+    let mut scattered = Vec3::zero();
+    for i in 0..volume.steps {
+        let point = ray.point_at((i as f32 + jitter) * step);
+        let density = volume.density_at(point);
+        if density <= 0.0 {
+            continue;
+        }
+        let step_transmittance = (-density * step).exp();
+        let in_scatter = scene.ambient_light + volume_lighting(volume, ray, point, scene, time, opts);
+        scattered = scattered + in_scatter * volume.color * (transmittance * (1.0 - step_transmittance));
+        transmittance *= step_transmittance;
+        if transmittance < MIN_CONTRIBUTION {
+            break;
+        }
+    }
+    color * transmittance + scattered
+}
+
+// Falling rain or snow: one column of drops per grid cell at a hashed spot, repeating
+// every `spacing` units and scrolling down at `speed`. Camera rays walk the cells they
+// cross (a 2D DDA) and blend in the drops they pass close to.
+struct Precipitation {
+    cell: f32,
+    spacing: f32,
+    speed: f32,
+    radius: f32,
+    half_length: f32, // Rain stretches into streaks; snowflakes are round
+    opacity: f32,
+    wobble: f32,      // Sideways drift of snowflakes
+    tint: Vec3,
+}
+
+const RAIN: Precipitation = Precipitation { cell: 0.5, spacing: 3.0, speed: 14.0, radius: 0.01, half_length: 0.3, opacity: 0.35, wobble: 0.0, tint: Vec3 { x: 0.7, y: 0.75, z: 0.85 } };
+const SNOW: Precipitation = Precipitation { cell: 0.6, spacing: 1.5, speed: 1.2, radius: 0.03, half_length: 0.0, opacity: 0.9, wobble: 0.25, tint: Vec3 { x: 1.0, y: 1.0, z: 1.0 } };
+// Drops nearer than this would smear across the lens; further ones blend into the haze
+const PRECIPITATION_NEAR: f32 = 1.0;
+const PRECIPITATION_DISTANCE: f32 = 24.0;
+const MAX_PRECIPITATION_CELLS: u32 = 96;
+// Roughly one pixel's angle at the default resolution; thinner drops are spread over it
+const PIXEL_ANGLE: f32 = 0.0015;
+
+impl Precipitation {
+    // Fraction of the view along `ray` the drops cover up to `max_t`
+    fn coverage(&self, ray: &Ray, max_t: f32, amount: f32, time: f32) -> f32 {
+        let (dx, dz) = (ray.direction.x, ray.direction.z);
+        if dx * dx + dz * dz < 1e-6 {
+            return 0.0; // Straight up or down the columns are seen end-on
+        }
+        let mut cell_x = (ray.origin.x / self.cell).floor();
+        let mut cell_z = (ray.origin.z / self.cell).floor();
+        let (step_x, step_z) = (dx.signum(), dz.signum());
+        let boundary = |cell: f32, step: f32, origin: f32, dir: f32| {
+            if dir == 0.0 { f32::INFINITY } else { ((cell + step.max(0.0)) * self.cell - origin) / dir }
+        };
+        let mut next_x = boundary(cell_x, step_x, ray.origin.x, dx);
+        let mut next_z = boundary(cell_z, step_z, ray.origin.z, dz);
+        let (delta_x, delta_z) = ((self.cell / dx).abs(), (self.cell / dz).abs());
+        
+        let mut t = 0.0f32;
+        let mut transmittance = 1.0;
+        for _ in 0..MAX_PRECIPITATION_CELLS {
+            let t_exit = next_x.min(next_z).min(max_t);
+            if t_exit > PRECIPITATION_NEAR {
+                transmittance *= 1.0 - self.drop_coverage(ray, cell_x, cell_z, t.max(PRECIPITATION_NEAR), t_exit, amount, time);
+            }
+            if t_exit >= max_t || transmittance < MIN_CONTRIBUTION {
+                break;
+            }
+            t = t_exit;
+            if next_x < next_z {
+                cell_x += step_x;
+                next_x += delta_x;
+            } else {
+                cell_z += step_z;
+                next_z += delta_z;
+            }
+        }
+        1.0 - transmittance
+    }
+    
+    // Coverage of the drop column in one cell, which the ray crosses between `t0` and `t1`
+    #[allow(clippy::too_many_arguments)]
+    fn drop_coverage(&self, ray: &Ray, cell_x: f32, cell_z: f32, t0: f32, t1: f32, amount: f32, time: f32) -> f32 {
+        let seed = Vec3::new(cell_x, 0.0, cell_z);
+        if hash(seed + Vec3::new(0.0, 1.0, 0.0)).abs() > amount {
+            return 0.0;
+        }
+        let mut column = Vec3::new(
+            (cell_x + 0.1 + 0.8 * hash(seed + Vec3::new(0.0, 2.0, 0.0)).abs()) * self.cell,
+            0.0,
+            (cell_z + 0.1 + 0.8 * hash(seed + Vec3::new(0.0, 3.0, 0.0)).abs()) * self.cell,
+        );
+        let stagger = hash(seed + Vec3::new(0.0, 4.0, 0.0)).abs() * self.spacing;
+        let fall = time * self.speed + stagger;
+        
+        let (mut t, mut offset) = self.closest_approach(ray, column, t0, t1, fall);
+        if self.wobble > 0.0 {
+            // Each flake sways on its own phase; re-aim at the one the ray passes
+            let flake = ((ray.point_at(t).y + fall) / self.spacing).floor();
+            let sway = time * 1.3 + flake * 2.1;
+            column = column + Vec3::new(sway.sin(), 0.0, sway.cos()) * self.wobble;
+            (t, offset) = self.closest_approach(ray, column, t0, t1, fall);
+        }
+        
+        // Thin or distant drops cover part of a pixel
+        let footprint = self.radius.max(PIXEL_ANGLE * t);
+        let vertical = (offset.y.abs() - self.half_length).max(0.0);
+        let distance = (offset.x * offset.x + offset.z * offset.z + vertical * vertical).sqrt();
+        let fraction = if self.half_length > 0.0 { self.radius / footprint } else { (self.radius / footprint).powi(2) };
+        self.opacity * fraction * (1.0 - distance / footprint).max(0.0)
+    }
+    
+    // Where along the ray (clamped to the cell) it passes nearest the column, and its offset
+    // there from the nearest drop centre
+    fn closest_approach(&self, ray: &Ray, column: Vec3, t0: f32, t1: f32, fall: f32) -> (f32, Vec3) {
+        let (dx, dz) = (ray.direction.x, ray.direction.z);
+        let to_column = column - ray.origin;
+        let t = ((to_column.x * dx + to_column.z * dz) / (dx * dx + dz * dz)).clamp(t0, t1);
+        let point = ray.point_at(t);
+        let along = (point.y + fall).rem_euclid(self.spacing) - self.spacing * 0.5;
+        (t, Vec3::new(point.x - column.x, along, point.z - column.z))
+    }
+}
+
+// Camera rays only: blends rain streaks and snowflakes over `color`, lit by the ambient
+// and directional light
+fn through_precipitation(ray: &Ray, distance: f32, color: Vec3, scene: &Scene, depth: i32, time: f32) -> Vec3 {
+    let weather = &scene.weather;
+    if depth > 0 || (weather.rain <= 0.0 && weather.snowfall <= 0.0) {
+        return color;
+    }
+    let light = scene.lights.iter()
+        .filter(|light| light.light_type.is_directional())
+        .fold(scene.ambient_light * 2.0, |sum, light| sum + light.color * (light.intensity * 0.15));
+    let max_t = distance.min(PRECIPITATION_DISTANCE);
+    let mut color = color;
+    for (layer, amount) in [(&RAIN, weather.rain), (&SNOW, weather.snowfall)] {
+        if amount > 0.0 {
+            color = color.lerp(light * layer.tint, layer.coverage(ray, max_t, amount, time));
+        }
+    }
+    color
+}
+
+// Light reaching `point` in the medium and scattered back along `ray`: the unsampled
+// lights plus one positional light from the tree, each shadowed like surface lighting
+fn volume_lighting(volume: &VolumetricFog, ray: &Ray, point: Vec3, scene: &Scene, time: f32, opts: &RenderOptions) -> Vec3 {
+    let mut color = Vec3::zero();
+    for light in unsampled_lights(scene, opts.shadow_mode) {
+        color = color + volume_light_sample(volume, ray, point, light, scene, time);
+    }
+    
+    if opts.shadow_mode == ShadowMode::Full {
+        if let Some((light, probability)) = scene.light_tree.sample(point, rand::random::<f32>()) {
+            color = color + volume_light_sample(volume, ray, point, scene.light(light), scene, time) / probability;
+        }
+    }
+    color
+}
+
+fn volume_light_sample(volume: &VolumetricFog, ray: &Ray, point: Vec3, light: &Light, scene: &Scene, time: f32) -> Vec3 {
+    let sample = light.sample(point);
+    if sample.intensity <= 0.01 {
+        return Vec3::zero();
+    }
+    
+    let shadow_ray = Ray::new(point, sample.direction);
+    let visibility = shadow_transmittance(&shadow_ray, sample.distance - SHADOW_EPSILON, scene, time);
+    if visibility.length_squared() <= 0.0 {
+        return Vec3::zero();
+    }
+    let mut attenuation = volume.transmittance(&shadow_ray, sample.distance);
+    if light.light_type.is_directional() {
+        attenuation *= cloud_shadow(scene, point, sample.direction);
+    }
+    light.color * visibility * (sample.intensity * attenuation * volume.phase(ray.direction.dot(sample.direction)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn shade_path(ray: &Ray, hit: &HitInfo, scene: &Scene, depth: i32, time: f32, rotation_y: f32, opts: &RenderOptions, emission: bool) -> Vec3 {
+    let material = scene.material(hit.material);
+    let tex_quality = if depth == 0 { TextureQuality::High } else { TextureQuality::Medium };
+    let albedo = material.sample_texture_quality(hit.uv, time, tex_quality);
+    
+    if material.is_emissive() {
+        let emitted = if emission && ray.direction.dot(hit.normal) < 0.0 { material.emissive * albedo } else { Vec3::zero() };
+        // Translucent emitters such as the portal also let the scene behind through
+        if material.is_transparent() && rand::random::<f32>() < material.transparency {
+            return emitted + calculate_refraction(ray, hit, scene, depth, time, rotation_y, opts);
         }
+        return emitted;
     }
+    
+    let surface = weathered_surface(ray, hit, material, albedo, scene, time, false);
+    let albedo = surface.albedo;
+    if surface.film > 0.0 && rand::random::<f32>() < surface.film {
+        return reflect_glossy(ray, hit, surface.alpha, scene, depth, time, rotation_y, opts);
+    }
+    
+    // Pick one lobe with probability equal to its weight, so the weight cancels
+    let reflect_prob = material.reflectivity.clamp(0.0, 1.0);
+    let transmit_prob = material.transparency.clamp(0.0, 1.0) * (1.0 - reflect_prob);
+    let choice = rand::random::<f32>();
+    if choice < reflect_prob {
+        return calculate_reflection(ray, hit, scene, depth, time, rotation_y, opts) * metal_tint(material, albedo);
+    }
+    if choice < reflect_prob + transmit_prob {
+        return calculate_refraction(ray, hit, scene, depth, time, rotation_y, opts);
+    }
+    
+    // Diffuse: direct light plus one cosine-weighted bounce (cos/pdf cancels with 1/pi)
+    let color = direct_lighting(ray, hit, &surface, scene, time, opts);
+
+    // Russian roulette on the path throughput past the first few bounces
+    let mut weight = albedo;
+    if depth >= RUSSIAN_ROULETTE_DEPTH {
+        let survive = albedo.x.max(albedo.y).max(albedo.z).clamp(0.05, 0.95);
+        if rand::random::<f32>() > survive {
+            return color;
+        }
+        weight = weight / survive;
+    }
+    if weight.length_squared() < MIN_CONTRIBUTION * MIN_CONTRIBUTION {
+        return color;
+    }
+    
+    // Bounce from the side the ray arrived on
+    let normal = if ray.direction.dot(hit.normal) > 0.0 { -hit.normal } else { hit.normal };
+    let mut bounce_dir = normal + random_unit_vector();
+    if bounce_dir.length_squared() < 1e-8 {
+        bounce_dir = normal;
+    }
+    let bounce_ray = Ray::new(hit.point + normal * 0.001, bounce_dir.normalize());
+    let emission = scene.emitters.is_empty() || opts.shadow_mode != ShadowMode::Full;
+    color + weight * trace_path(&bounce_ray, scene, depth + 1, time, rotation_y, opts, emission, None)
 }
 
 fn intersect_scene(ray: &Ray, scene: &Scene, time: f32) -> Option<HitInfo> {
+    let closest_hit = intersect_objects(ray, scene, time);
+    // Particles live outside the BVH and are tested against whatever the geometry hit
+    scene.particles.iter().fold(closest_hit, |closest, system| {
+        let max_t = closest.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+        system.intersect(ray, max_t).or(closest)
+    })
+}
+
+fn intersect_objects(ray: &Ray, scene: &Scene, time: f32) -> Option<HitInfo> {
     if let Some(bvh) = &scene.bvh {
-        intersect_bvh(ray, bvh, scene, time)
+        bvh.intersect(ray, &scene.objects, time)
     } else {
         let mut closest_hit: Option<HitInfo> = None;
         let mut closest_t = f32::INFINITY;
@@ -632,20 +1744,40 @@ fn intersect_scene(ray: &Ray, scene: &Scene, time: f32) -> Option<HitInfo> {
     }
 }
 
+// Shadow-ray counterpart of intersect_scene: true if anything lies between 0.001 and max_t
+fn occluded_scene(ray: &Ray, max_t: f32, scene: &Scene, time: f32) -> bool {
+    let geometry = if let Some(bvh) = &scene.bvh {
+        bvh.occluded(ray, max_t, &scene.objects, time)
+    } else {
+        scene.objects.iter().any(|object| object.occluded(ray, max_t, time))
+    };
+    geometry || scene.particles.iter().any(|system| system.occluded(ray, max_t))
+}
+
 fn shade_hit(ray: &Ray, hit: &HitInfo, scene: &Scene, depth: i32, time: f32, rotation_y: f32, opts: &RenderOptions) -> Vec3 {
     let mut color = Vec3::zero();
     
     // Sample material texture
     // Lower texture quality under heavy recursion or distance to save cost
     let far = hit.t > opts.far_simplify_distance;
-    let tex_quality = if far { TextureQuality::Low } else if depth >= 3 { TextureQuality::Low } else if depth >= 1 { TextureQuality::Medium } else { TextureQuality::High };
-    let albedo = hit.material.sample_texture_quality(hit.uv, time, tex_quality);
+    let tex_quality = if far || depth >= 3 { TextureQuality::Low } else if depth >= 1 { TextureQuality::Medium } else { TextureQuality::High };
+    let material = scene.material(hit.material);
+    let albedo = material.sample_texture_quality(hit.uv, time, tex_quality);
     
-    // Emissive materials
-    if hit.material.is_emissive() {
-        return hit.material.emissive * albedo;
+    // Emissive materials; translucent ones (the portal) glow from the faces rays enter
+    // and tint what lies behind them
+    if material.is_emissive() {
+        let entering = ray.direction.dot(hit.normal) < 0.0;
+        let mut color = if entering { material.emissive * albedo } else { Vec3::zero() };
+        if material.is_transparent() && depth < opts.max_depth {
+            color = color + calculate_refraction(ray, hit, scene, depth, time, rotation_y, opts) * material.transparency;
+        }
+        return color;
     }
     
+    let surface = weathered_surface(ray, hit, material, albedo, scene, time, far);
+    let albedo = surface.albedo;
+    
     // Ambient lighting
     color = color + scene.ambient_light * albedo;
     
@@ -653,100 +1785,271 @@ fn shade_hit(ray: &Ray, hit: &HitInfo, scene: &Scene, depth: i32, time: f32, rot
     if far {
         // For far surfaces, only add an unshadowed directional light to reduce cost
         for light in &scene.lights {
-            if let LightType::Directional(_) = light.light_type {
+            if light.light_type.is_directional() {
                 let light_dir = light.get_light_direction(hit.point);
                 let n_dot_l = hit.normal.dot(light_dir).max(0.0);
-                let intensity = light.intensity;
+                let intensity = light.intensity * cloud_shadow(scene, hit.point, light_dir);
                 color = color + albedo * light.color * intensity * n_dot_l / std::f32::consts::PI;
             }
         }
     } else {
-        for light in &scene.lights {
-            match (&opts.shadow_mode, &light.light_type) {
-                (ShadowMode::None, _) => {},
-                (ShadowMode::SunOnly, LightType::Point) => {},
-                _ => { color = color + calculate_direct_lighting(ray, hit, light, albedo, scene, time, opts); }
-            }
-        }
+        color = color + direct_lighting(ray, hit, &surface, scene, time, opts);
+    }
+    
+    // Wet surfaces mirror their surroundings
+    if !far && surface.film > MIN_CONTRIBUTION && !material.is_reflective() && depth < opts.max_depth {
+        let film = reflect_glossy(ray, hit, surface.alpha, scene, depth, time, rotation_y, opts);
+        color = color.lerp(film, surface.film);
     }
     
     // Reflection
-    if !far && hit.material.is_reflective() && depth < opts.max_depth {
+    if !far && material.is_reflective() && depth < opts.max_depth {
         let reflect_contribution = calculate_reflection(ray, hit, scene, depth, time, rotation_y, opts);
-        color = color + reflect_contribution * hit.material.reflectivity;
+        color = color + reflect_contribution * metal_tint(material, albedo) * material.reflectivity;
     }
     
     // Refraction/Transmission
-    if !far && hit.material.is_transparent() && depth < opts.max_depth {
+    if !far && material.is_transparent() && depth < opts.max_depth {
         let refract_contribution = calculate_refraction(ray, hit, scene, depth, time, rotation_y, opts);
-        color = color.lerp(refract_contribution, hit.material.transparency);
+        color = color.lerp(refract_contribution, material.transparency);
     }
     
     color
 }
 
-fn calculate_direct_lighting(ray: &Ray, hit: &HitInfo, light: &Light, albedo: Vec3, scene: &Scene, time: f32, _opts: &RenderOptions) -> Vec3 {
-    let light_dir = light.get_light_direction(hit.point);
-    let light_distance = match light.light_type {
-        LightType::Directional(_) => f32::INFINITY,
-        _ => (light.position - hit.point).length(),
-    };
+// What the lighting code reads at a hit once weather has changed the material: textured
+// albedo, GGX alpha, and `film`, the fraction a wet surface reflects like a mirror
+#[derive(Clone, Copy)]
+struct Surface {
+    albedo: Vec3,
+    alpha: f32,
+    film: f32,
+}
+
+// Rain darkens surfaces open to the sky, smooths them and leaves puddles on flat tops;
+// lying snow whitens the faces that look up, in patches until it has built up. Sky
+// exposure costs one any-hit ray straight up, skipped for far surfaces.
+fn weathered_surface(ray: &Ray, hit: &HitInfo, material: &Material, albedo: Vec3, scene: &Scene, time: f32, far: bool) -> Surface {
+    let plain = Surface { albedo, alpha: material.alpha(), film: 0.0 };
+    let weather = &scene.weather;
+    let exposed_kind = !material.is_transparent() && !material.is_emissive() && hit.normal.y > -0.5;
+    if (weather.wetness < 0.01 && weather.snow_cover < 0.01) || !exposed_kind {
+        return plain;
+    }
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    if !far && occluded_scene(&Ray::new(hit.point + hit.normal * 0.01, up), SKY_EXPOSURE_DISTANCE, scene, time) {
+        return plain;
+    }
     
-    // Early out if not facing light
-    let n_dot_l = hit.normal.dot(light_dir).max(0.0);
-    if n_dot_l <= 0.0 { return Vec3::zero(); }
+    let facing_up = hit.normal.y.max(0.0);
+    let patches = noise(hit.point * 1.3) * 0.5 + 0.5;
+    let snow_edge = 1.0 - weather.snow_cover * 1.3;
+    let snow = Vec3::smoothstep(0.6, 0.9, facing_up) * Vec3::smoothstep(snow_edge - 0.05, snow_edge + 0.05, patches);
+    let wet = weather.wetness * (0.5 + 0.5 * facing_up) * (1.0 - snow);
     
-    // Shadow test
-    let shadow_ray = Ray::new(hit.point + hit.normal * 0.001, light_dir);
-    let in_shadow = intersect_scene(&shadow_ray, scene, time)
-        .map(|shadow_hit| shadow_hit.t < light_distance)
-        .unwrap_or(false);
+    let albedo = (albedo * (1.0 - WET_DARKENING * wet)).lerp(snow_color(), snow);
+    let wet_alpha = material.alpha() + (WET_ALPHA - material.alpha()) * wet;
+    let alpha = wet_alpha + (SNOW_ALPHA - wet_alpha) * snow;
+    // Water film: Fresnel reflection everywhere, puddles where the noise dips on flat tops
+    let cos_theta = (-ray.direction).dot(hit.normal).abs();
+    let puddle = Vec3::smoothstep(0.9, 1.0, facing_up) * Vec3::smoothstep(0.35, 0.3, patches);
+    let film = wet * (schlick(cos_theta, WATER_IOR) + (PUDDLE_REFLECTANCE - schlick(cos_theta, WATER_IOR)) * puddle);
+    Surface { albedo, alpha, film }
+}
+
+fn snow_color() -> Vec3 {
+    Vec3::new(0.9, 0.92, 0.96)
+}
+
+// Scene lights shaded one by one instead of picked from the light tree. Full mode takes
+// only the directional ones; SunOnly skips the tree, so like before it shades every
+// scene light except point lights (emissive blocks are left to the tree)
+fn unsampled_lights(scene: &Scene, mode: ShadowMode) -> impl Iterator<Item = &Light> {
+    scene.lights.iter().filter(move |light| match mode {
+        ShadowMode::None => false,
+        ShadowMode::SunOnly => !matches!(light.light_type, LightType::Point),
+        ShadowMode::Full => light.light_type.is_directional(),
+    })
+}
+
+// The unsampled lights, plus in Full mode LIGHT_SAMPLES positional lights picked from
+// the light tree, so the cost doesn't grow with the number of lights
+fn direct_lighting(ray: &Ray, hit: &HitInfo, surface: &Surface, scene: &Scene, time: f32, opts: &RenderOptions) -> Vec3 {
+    let mut color = Vec3::zero();
+    for light in unsampled_lights(scene, opts.shadow_mode) {
+        color = color + calculate_direct_lighting(ray, hit, light, surface, scene, time, opts);
+    }
     
-    if in_shadow {
-        return Vec3::zero();
+    if opts.shadow_mode == ShadowMode::Full {
+        for _ in 0..LIGHT_SAMPLES {
+            if let Some((light, probability)) = scene.light_tree.sample(hit.point, rand::random::<f32>()) {
+                let contribution = calculate_direct_lighting(ray, hit, scene.light(light), surface, scene, time, opts);
+                color = color + contribution / (probability * LIGHT_SAMPLES as f32);
+            }
+        }
+    }
+    color
+}
+
+// Metals colour their reflections with the albedo; dielectrics reflect untinted
+fn metal_tint(material: &Material, albedo: Vec3) -> Vec3 {
+    Vec3::one().lerp(albedo, material.metallic.clamp(0.0, 1.0))
+}
+
+// Averages `light.sample_count()` shadowed samples, so area lights cast penumbrae
+fn calculate_direct_lighting(ray: &Ray, hit: &HitInfo, light: &Light, surface: &Surface, scene: &Scene, time: f32, _opts: &RenderOptions) -> Vec3 {
+    let samples = light.sample_count();
+    let mut total = Vec3::zero();
+    for _ in 0..samples {
+        let sample = light.sample(hit.point);
+        total = total + shade_light_sample(ray, hit, &sample, light.color, surface, scene, time);
     }
     
-    // Light attenuation and spot factor
-    let attenuation = light.get_attenuation(hit.point);
-    let spot_factor = light.get_spot_factor(hit.point);
-    let light_intensity = light.intensity * attenuation * spot_factor;
+    // Directional lights shine from above the clouds; one lookup serves every disc sample
+    if light.light_type.is_directional() && total.length_squared() > 0.0 {
+        total = total * cloud_shadow(scene, hit.point, light.get_light_direction(hit.point));
+    }
+    total / samples as f32
+}
+
+fn shade_light_sample(ray: &Ray, hit: &HitInfo, sample: &LightSample, light_color: Vec3, surface: &Surface, scene: &Scene, time: f32) -> Vec3 {
+    let light_dir = sample.direction;
+    let light_intensity = sample.intensity;
     // Skip lights that contribute too little
     if light_intensity <= 0.01 { return Vec3::zero(); }
     
-    // Lambertian diffuse
-    let diffuse = albedo * light.color * light_intensity * n_dot_l / std::f32::consts::PI;
+    // Early out if not facing light
+    let n_dot_l = hit.normal.dot(light_dir).max(0.0);
+    if n_dot_l <= 0.0 { return Vec3::zero(); }
     
-    // Blinn-Phong specular
+    // Shadow test; glass, water and portal blocks pass tinted light
+    let shadow_ray = Ray::new(hit.point + hit.normal * 0.001, light_dir);
+    let transmittance = shadow_transmittance(&shadow_ray, sample.distance - SHADOW_EPSILON, scene, time);
+    if transmittance.length_squared() <= 0.0 {
+        return Vec3::zero();
+    }
+
+    // Cook-Torrance: GGX distribution, Smith masking and Schlick Fresnel
+    let material = scene.material(hit.material);
     let view_dir = (-ray.direction).normalize();
     let half_dir = (view_dir + light_dir).normalize();
+    let n_dot_v = hit.normal.dot(view_dir).max(1e-4);
     let n_dot_h = hit.normal.dot(half_dir).max(0.0);
+    let v_dot_h = view_dir.dot(half_dir).max(0.0);
     
-    let shininess = (1.0 - hit.material.roughness) * 256.0 + 1.0;
-    let specular_strength = hit.material.specular;
-    let specular = light.color * light_intensity * n_dot_h.powf(shininess) * specular_strength;
+    let (albedo, alpha) = (surface.albedo, surface.alpha);
+    let f0 = material.specular_f0(albedo);
+    let fresnel = f0 + (Vec3::one() - f0) * (1.0 - v_dot_h).powi(5);
+    let specular = fresnel * (ggx_distribution(n_dot_h, alpha) * smith_g1(n_dot_v, alpha) * smith_g1(n_dot_l, alpha) / (4.0 * n_dot_v * n_dot_l));
     
-    diffuse + specular
+    // Metals have no diffuse lobe; dielectrics lose what the Fresnel term reflects
+    let diffuse = (Vec3::one() - fresnel) * albedo * ((1.0 - material.metallic) / std::f32::consts::PI);
+    
+    (diffuse + specular) * light_color * transmittance * (light_intensity * n_dot_l)
+}
+
+// Light left at the end of a shadow ray. Opaque hits block it; transparent ones let
+// `transparency` through where the ray enters them and absorb along the path inside.
+// The any-hit test runs first so unshadowed rays cost no more than before.
+fn shadow_transmittance(ray: &Ray, max_t: f32, scene: &Scene, time: f32) -> Vec3 {
+    if !occluded_scene(ray, max_t, scene, time) {
+        return Vec3::one();
+    }
+    
+    let mut throughput = Vec3::one();
+    let mut segment = *ray;
+    let mut remaining = max_t;
+    for _ in 0..MAX_SHADOW_CROSSINGS {
+        let hit = match intersect_scene(&segment, scene, time) {
+            Some(hit) if hit.t < remaining => hit,
+            _ => return throughput,
+        };
+        let material = scene.material(hit.material);
+        if !material.is_transparent() {
+            return Vec3::zero();
+        }
+        
+        if segment.direction.dot(hit.normal) < 0.0 {
+            throughput = throughput * material.transparency;
+        } else {
+            throughput = throughput * material.transmittance(hit.t);
+        }
+        if throughput.x.max(throughput.y).max(throughput.z) < MIN_CONTRIBUTION {
+            return Vec3::zero();
+        }
+        
+        segment = Ray::new(hit.point + segment.direction * 0.001, segment.direction);
+        remaining -= hit.t + 0.001;
+    }
+    Vec3::zero()
+}
+
+fn cloud_shadow(scene: &Scene, point: Vec3, light_dir: Vec3) -> f32 {
+    scene.skybox.as_ref().map_or(1.0, |skybox| skybox.cloud_shadow(point, light_dir))
+}
+
+fn ggx_distribution(n_dot_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (std::f32::consts::PI * d * d)
+}
+
+// Smith masking for one direction (separable GGX form)
+fn smith_g1(n_dot_x: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    2.0 * n_dot_x / (n_dot_x + (a2 + (1.0 - a2) * n_dot_x * n_dot_x).sqrt())
 }
 
+// GGX-distributed microfacet normal around `normal`
+fn sample_ggx_half_vector(normal: Vec3, alpha: f32) -> Vec3 {
+    let mut rng = rand::thread_rng();
+    let (u1, u2) = (rng.gen::<f32>(), rng.gen::<f32>());
+    let cos_theta = ((1.0 - u1) / (1.0 + (alpha * alpha - 1.0) * u1)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u2;
+    
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    (tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + normal * cos_theta).normalize()
+}
+
+// Glossy reflection: mirrors about a GGX-sampled microfacet normal, so rough surfaces
+// blur what they reflect as samples accumulate (smooth ones stay perfect mirrors)
 fn calculate_reflection(ray: &Ray, hit: &HitInfo, scene: &Scene, depth: i32, time: f32, rotation_y: f32, opts: &RenderOptions) -> Vec3 {
-    let reflect_dir = ray.direction.reflect(hit.normal);
+    reflect_glossy(ray, hit, scene.material(hit.material).alpha(), scene, depth, time, rotation_y, opts)
+}
+
+// Reflection with GGX roughness `alpha`, which may differ from the material's (wet surfaces)
+#[allow(clippy::too_many_arguments)]
+fn reflect_glossy(ray: &Ray, hit: &HitInfo, alpha: f32, scene: &Scene, depth: i32, time: f32, rotation_y: f32, opts: &RenderOptions) -> Vec3 {
+    let mirror_dir = ray.direction.reflect(hit.normal);
+    let reflect_dir = if alpha <= 0.002 {
+        mirror_dir
+    } else {
+        let glossy_dir = ray.direction.reflect(sample_ggx_half_vector(hit.normal, alpha));
+        // Directions under the surface fall back to the mirror one
+        if glossy_dir.dot(hit.normal) * mirror_dir.dot(hit.normal) > 0.0 { glossy_dir } else { mirror_dir }
+    };
     let reflect_ray = Ray::new(hit.point + hit.normal * 0.001, reflect_dir);
     trace_ray(&reflect_ray, scene, depth + 1, time, rotation_y, opts)
 }
 
 fn calculate_refraction(ray: &Ray, hit: &HitInfo, scene: &Scene, depth: i32, time: f32, rotation_y: f32, opts: &RenderOptions) -> Vec3 {
     let entering = ray.direction.dot(hit.normal) < 0.0;
+    let refraction_index = scene.material(hit.material).refraction_index;
     let eta = if entering {
-        1.0 / hit.material.refraction_index
+        1.0 / refraction_index
     } else {
-        hit.material.refraction_index
+        refraction_index
     };
     
     let normal = if entering { hit.normal } else { -hit.normal };
     
     if let Some(refract_dir) = (-ray.direction).refract(normal, eta) {
         let refract_ray = Ray::new(hit.point - normal * 0.001, refract_dir);
-        trace_ray(&refract_ray, scene, depth + 1, time, rotation_y, opts)
+        // Entering rays travel inside the material until their next hit
+        let material = scene.material(hit.material);
+        let medium = if entering && material.is_absorbing() { Some(material) } else { None };
+        trace_ray_in(&refract_ray, scene, depth + 1, time, rotation_y, opts, medium)
     } else {
         // Total internal reflection
         calculate_reflection(ray, hit, scene, depth, time, rotation_y, opts)
@@ -769,4 +2072,84 @@ fn random_in_unit_sphere() -> Vec3 {
 
 fn random_unit_vector() -> Vec3 {
     random_in_unit_sphere().normalize()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // Exponentially spaced spheres: with two bins, each SAH split peels off the farthest ones
+    fn chain(count: i32) -> Vec<Box<dyn Primitive>> {
+        (0..count).map(|i| Box::new(Sphere::new(Vec3::new(2f32.powi(i), 0.0, 0.0), 0.25, 0)) as Box<dyn Primitive>).collect()
+    }
+    
+    #[test]
+    fn degenerate_bvh_is_capped_to_the_traversal_stack() {
+        let objects = chain(120);
+        let tree = build_bvh(&objects, &BvhConfig { bins: 2, ..BvhConfig::default() }).unwrap();
+        let stats = tree.stats();
+        assert!(stats.max_depth <= BVH_MAX_DEPTH, "depth {}", stats.max_depth);
+        assert_eq!(stats.primitives, 120);
+        
+        // The nearest spheres are split off last, so they end up in the capped leaf
+        let bvh = FlatBvh::from_tree(&tree);
+        let ray = Ray::new(Vec3::new(8.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = bvh.intersect(&ray, &objects, 0.0).unwrap();
+        assert!((hit.t - 9.75).abs() < 1e-3, "t {}", hit.t);
+        assert!(bvh.occluded(&ray, 20.0, &objects, 0.0));
+    }
+    
+    #[test]
+    fn sah_and_median_bvhs_agree_with_brute_force() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut point = |scale: f32| Vec3::new(rng.gen_range(-scale..scale), rng.gen_range(-scale..scale), rng.gen_range(-scale..scale));
+        
+        // Clustered and overlapping shapes of mixed sizes
+        let mut objects: Vec<Box<dyn Primitive>> = Vec::new();
+        for i in 0..300 {
+            let center = point(20.0) * if i % 3 == 0 { 0.2 } else { 1.0 };
+            let size = 0.1 + (point(1.0).x + 1.0) * 0.8;
+            if i % 4 == 0 {
+                objects.push(Box::new(Cube::new(center, size, 0)));
+            } else {
+                objects.push(Box::new(Sphere::new(center, size, 0)));
+            }
+        }
+        let rays: Vec<Ray> = (0..500).map(|_| {
+            let origin = point(30.0);
+            Ray::new(origin, point(10.0) - origin)
+        }).collect();
+        
+        let configs = [BvhConfig::default(), BvhConfig { max_leaf_size: 1, bins: 4, ..BvhConfig::default() }, BvhConfig::median()];
+        let bvhs: Vec<FlatBvh> = configs.iter().map(|config| FlatBvh::from_tree(&build_bvh(&objects, config).unwrap())).collect();
+        let mut hits = 0;
+        for ray in &rays {
+            let nearest = objects.iter().filter_map(|object| object.intersect(ray, 0.0)).map(|hit| hit.t).min_by(f32::total_cmp);
+            hits += nearest.is_some() as usize;
+            for (bvh, config) in bvhs.iter().zip(&configs) {
+                let t = bvh.intersect(ray, &objects, 0.0).map(|hit| hit.t);
+                assert_eq!(t, nearest, "{config:?}");
+                let max_t = nearest.map_or(100.0, |t| t + 0.01);
+                assert_eq!(bvh.occluded(ray, max_t, &objects, 0.0), nearest.is_some(), "{config:?}");
+            }
+        }
+        assert!(hits > 100 && hits < rays.len(), "{hits} hits");
+    }
+    
+    #[test]
+    fn sun_only_shadows_skip_just_point_lights() {
+        let mut scene = Scene::new();
+        let white = Vec3::one();
+        scene.lights = vec![
+            Light::directional(Vec3::new(0.0, -1.0, 0.0), white, 1.0),
+            Light::point(Vec3::new(0.0, 5.0, 0.0), white, 1.0),
+            Light::spot(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), white, 1.0, 0.3, 0.5),
+        ];
+        let shaded = |mode| unsampled_lights(&scene, mode).map(|light| std::mem::discriminant(&light.light_type)).collect::<Vec<_>>();
+        let kind = |i: usize| std::mem::discriminant(&scene.lights[i].light_type);
+        assert_eq!(shaded(ShadowMode::SunOnly), vec![kind(0), kind(2)]);
+        assert_eq!(shaded(ShadowMode::Full), vec![kind(0)]);
+        assert!(shaded(ShadowMode::None).is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::math::Vec3;
+use crate::blocks::{BlockFace, BlockRegistry};
+use crate::texture::{srgb_to_linear, AnimatedImage, ImageTexture, Texture};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// Biome colours for the grayscale vanilla textures (plains defaults, sRGB)
+const GRASS_TINT: [u8; 3] = [0x91, 0xBD, 0x59];
+const FOLIAGE_TINT: [u8; 3] = [0x77, 0xAB, 0x2F];
+const WATER_TINT: [u8; 3] = [0x3F, 0x76, 0xE4];
+
+// Which pack texture goes on each face of a registry block
+struct BlockTextures {
+    block: &'static str,
+    top: &'static str,
+    side: &'static str,
+    bottom: &'static str,
+    tint: Option<[u8; 3]>,
+}
+
+const fn same(block: &'static str, texture: &'static str) -> BlockTextures {
+    BlockTextures { block, top: texture, side: texture, bottom: texture, tint: None }
+}
+
+const BLOCK_TEXTURES: &[BlockTextures] = &[
+    // Only the top of grass is tinted; the side texture already carries its colour
+    BlockTextures { block: "minecraft:grass_block", top: "grass_block_top", side: "grass_block_side", bottom: "dirt", tint: None },
+    same("minecraft:dirt", "dirt"),
+    same("minecraft:stone", "stone"),
+    same("minecraft:oak_planks", "oak_planks"),
+    BlockTextures { block: "minecraft:oak_log", top: "oak_log_top", side: "oak_log", bottom: "oak_log_top", tint: None },
+    BlockTextures { tint: Some(FOLIAGE_TINT), ..same("minecraft:oak_leaves", "oak_leaves") },
+    BlockTextures { tint: Some(WATER_TINT), ..same("minecraft:water", "water_still") },
+    same("minecraft:glass", "glass"),
+    same("minecraft:diamond_block", "diamond_block"),
+    same("minecraft:obsidian", "obsidian"),
+    same("minecraft:glowstone", "glowstone"),
+    same("minecraft:nether_portal", "nether_portal"),
+    same("minecraft:campfire", "campfire_fire"),
+    same("minecraft:torch", "torch"),
+];
+
+// `animation` section of a .mcmeta file
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationMeta {
+    pub frametime: u32,
+    pub frames: Option<Vec<(usize, Option<u32>)>>, // (frame index, per-frame time override)
+    pub interpolate: bool,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl AnimationMeta {
+    // Returns None when the file has no `animation` section
+    pub fn parse(text: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let json: serde_json::Value = serde_json::from_str(text)?;
+        let animation = match json.get("animation") {
+            Some(animation) => animation,
+            None => return Ok(None),
+        };
+        let uint = |key: &str| animation.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+
+        let frames = match animation.get("frames") {
+            Some(serde_json::Value::Array(entries)) => {
+                let mut frames = Vec::with_capacity(entries.len());
+                for entry in entries {
+                    let frame = match entry {
+                        serde_json::Value::Number(n) => n.as_u64().map(|index| (index as usize, None)),
+                        serde_json::Value::Object(o) => o.get("index").and_then(|v| v.as_u64()).map(|index| {
+                            (index as usize, o.get("time").and_then(|v| v.as_u64()).map(|t| t as u32))
+                        }),
+                        _ => None,
+                    };
+                    frames.push(frame.ok_or("invalid animation frame entry")?);
+                }
+                Some(frames)
+            },
+            Some(_) => return Err("animation frames must be an array".into()),
+            None => None,
+        };
+
+        Ok(Some(Self {
+            frametime: uint("frametime").unwrap_or(1).max(1),
+            frames,
+            interpolate: animation.get("interpolate").and_then(|v| v.as_bool()).unwrap_or(false),
+            width: uint("width"),
+            height: uint("height"),
+        }))
+    }
+}
+
+// Block textures of an unpacked resource pack, keyed by file stem ("stone", "water_still")
+pub struct ResourcePack {
+    textures: HashMap<String, Texture>,
+}
+
+impl ResourcePack {
+    pub fn load(root: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let block_dir = root.join("assets/minecraft/textures/block");
+        let mut textures = HashMap::new();
+
+        for entry in fs::read_dir(&block_dir).map_err(|e| format!("{}: {}", block_dir.display(), e))? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("png") {
+                continue;
+            }
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+
+            // A broken file only costs its own texture, not the whole pack
+            let image = match image::open(&path) {
+                Ok(image) => image,
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", path.display(), e);
+                    continue;
+                },
+            };
+            let meta_path = path.with_extension("png.mcmeta");
+            let meta = if meta_path.exists() {
+                match fs::read_to_string(&meta_path).map_err(|e| e.into()).and_then(|text| AnimationMeta::parse(&text)) {
+                    Ok(meta) => meta,
+                    Err(e) => {
+                        eprintln!("Ignoring {}: {} (loading it as a still texture)", meta_path.display(), e);
+                        None
+                    },
+                }
+            } else {
+                None
+            };
+
+            let texture = match meta {
+                Some(meta) => Texture::animated_image(split_animation(&image, &meta)),
+                None => Texture::from_image(ImageTexture::from_image(&image)),
+            };
+            textures.insert(name, texture);
+        }
+
+        Ok(Self { textures })
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&Texture> {
+        self.textures.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    // Swaps the procedural textures of known blocks for the pack's; faces the pack
+    // doesn't provide keep their procedural look. Returns the number of faces replaced.
+    pub fn apply(&self, registry: &mut BlockRegistry) -> usize {
+        let mut replaced = 0;
+        for mapping in BLOCK_TEXTURES {
+            let id = match registry.id(mapping.block) {
+                Some(id) => id,
+                None => continue,
+            };
+            let def = registry.get_mut(id);
+            for (face, name) in [(BlockFace::Top, mapping.top), (BlockFace::Side, mapping.side), (BlockFace::Bottom, mapping.bottom)] {
+                let texture = match self.texture(name) {
+                    Some(texture) => texture,
+                    None => continue,
+                };
+                let tint = match (face, mapping.block) {
+                    (BlockFace::Top, "minecraft:grass_block") => Some(GRASS_TINT),
+                    _ => mapping.tint,
+                };
+                let texture = match tint {
+                    Some(tint) => tinted(texture, tint),
+                    None => texture.clone(),
+                };
+                let material = match face {
+                    BlockFace::Top => &mut def.faces.top,
+                    BlockFace::Bottom => &mut def.faces.bottom,
+                    BlockFace::Side => &mut def.faces.side,
+                };
+                material.texture = Some(texture);
+                replaced += 1;
+            }
+        }
+        replaced
+    }
+}
+
+// Cuts a vertical strip into frames and expands the frame list into (frame, ticks)
+fn split_animation(image: &image::DynamicImage, meta: &AnimationMeta) -> AnimatedImage {
+    let frame_width = meta.width.unwrap_or_else(|| image.width().min(image.height())).clamp(1, image.width());
+    let frame_height = meta.height.unwrap_or(frame_width).clamp(1, image.height());
+    let columns = (image.width() / frame_width).max(1);
+    let count = (columns * (image.height() / frame_height)).max(1);
+
+    let frames: Vec<ImageTexture> = (0..count)
+        .map(|i| {
+            let (x, y) = ((i % columns) * frame_width, (i / columns) * frame_height);
+            ImageTexture::from_image(&image.crop_imm(x, y, frame_width, frame_height))
+        })
+        .collect();
+
+    let sequence = match &meta.frames {
+        Some(list) => list
+            .iter()
+            .filter(|(index, _)| *index < frames.len())
+            .map(|&(index, time)| (index, time.unwrap_or(meta.frametime)))
+            .collect(),
+        None => (0..frames.len()).map(|index| (index, meta.frametime)).collect(),
+    };
+
+    AnimatedImage { frames, sequence, interpolate: meta.interpolate }
+}
+
+fn tinted(texture: &Texture, tint: [u8; 3]) -> Texture {
+    let tint = Vec3::new(
+        srgb_to_linear(tint[0] as f32 / 255.0),
+        srgb_to_linear(tint[1] as f32 / 255.0),
+        srgb_to_linear(tint[2] as f32 / 255.0),
+    );
+    let mut texture = texture.clone();
+    match &mut texture.texture_type {
+        crate::texture::TextureType::Image(image) => *image = image.tinted(tint),
+        crate::texture::TextureType::AnimatedImage(animation) => {
+            animation.frames = animation.frames.iter().map(|frame| frame.tinted(tint)).collect();
+        },
+        _ => {}
+    }
+    texture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A vertical strip of `count` square 16x16 frames
+    fn strip(count: u32) -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::new(16, 16 * count))
+    }
+
+    #[test]
+    fn missing_animation_section_is_not_animated() {
+        assert_eq!(AnimationMeta::parse(r#"{"texture": {"blur": true}}"#).unwrap(), None);
+    }
+
+    #[test]
+    fn default_frametime_plays_every_frame_for_one_tick() {
+        let meta = AnimationMeta::parse(r#"{"animation": {}}"#).unwrap().unwrap();
+        assert_eq!(meta.frametime, 1);
+        assert_eq!(meta.frames, None);
+        assert!(!meta.interpolate);
+
+        let animation = split_animation(&strip(3), &meta);
+        assert_eq!(animation.frames.len(), 3);
+        assert_eq!(animation.sequence, vec![(0, 1), (1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn explicit_frames_override_order_and_time() {
+        let text = r#"{"animation": {"frametime": 3, "interpolate": true, "frames": [2, {"index": 0, "time": 7}, 5]}}"#;
+        let meta = AnimationMeta::parse(text).unwrap().unwrap();
+        assert_eq!(meta.frames, Some(vec![(2, None), (0, Some(7)), (5, None)]));
+        assert!(meta.interpolate);
+
+        // Frame 5 doesn't exist in a three-frame strip and is dropped
+        let animation = split_animation(&strip(3), &meta);
+        assert_eq!(animation.sequence, vec![(2, 3), (0, 7)]);
+    }
+
+    #[test]
+    fn malformed_metadata_is_an_error() {
+        assert!(AnimationMeta::parse(r#"{"animation": {"#).is_err());
+        assert!(AnimationMeta::parse(r#"{"animation": {"frames": {"index": 0}}}"#).is_err());
+        assert!(AnimationMeta::parse(r#"{"animation": {"frames": ["first"]}}"#).is_err());
+    }
+}
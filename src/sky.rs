@@ -0,0 +1,98 @@
+use crate::math::Vec3;
+
+// Preetham zenith luminance comes in kcd/m²; this maps a clear noon sky to ~0.3-0.8
+const RADIANCE_SCALE: f32 = 0.05;
+
+// Wavelengths (µm) standing in for the red, green and blue channels
+const WAVELENGTHS: [f32; 3] = [0.68, 0.55, 0.44];
+
+// Analytic daylight sky (Preetham, Shirley & Smits 1999) for one sun position and
+// turbidity (2 = very clear, 10 = hazy). Sky radiance comes from the Perez distribution
+// scaled by the zenith colour; the sun's own colour is its light after Rayleigh and
+// aerosol extinction along the air mass it crosses, reddening towards the horizon.
+#[derive(Clone)]
+pub struct PreethamSky {
+    pub sun_direction: Vec3, // Towards the sun
+    pub turbidity: f32,
+    perez: [[f32; 5]; 3], // Y, x, y coefficients A..E
+    zenith: [f32; 3],     // Y, x, y at the zenith
+    normalizer: [f32; 3], // Perez F at the zenith, so radiance(zenith) == zenith
+}
+
+impl PreethamSky {
+    pub fn new(sun_direction: Vec3, turbidity: f32) -> Self {
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity.clamp(1.7, 10.0);
+        // Below the horizon the model breaks down; keep evaluating it at sunset
+        let theta_s = sun_direction.y.clamp(0.0, 1.0).acos().min(std::f32::consts::FRAC_PI_2 - 0.01);
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (th, th2, th3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let normalizer = [0, 1, 2].map(|i| perez_f(&perez[i], 0.0, theta_s));
+        Self {
+            sun_direction,
+            turbidity: t,
+            perez,
+            zenith: [zenith_luminance.max(0.0), zenith_x, zenith_y],
+            normalizer,
+        }
+    }
+
+    // Linear RGB sky radiance in scene units; directions below the horizon see the horizon
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+        let cos_theta = direction.y.max(0.01);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez_f(&self.perez[i], cos_theta.acos(), gamma) / self.normalizer[i]
+        });
+        xyy_to_rgb(x, y, luminance) * RADIANCE_SCALE
+    }
+
+    // Colour of direct sunlight at the ground: white outside the atmosphere, attenuated by
+    // Rayleigh and aerosol optical depth over the relative air mass (Kasten & Young)
+    pub fn sun_transmittance(&self) -> Vec3 {
+        let elevation = self.sun_direction.y.clamp(0.0, 1.0).asin().to_degrees();
+        let air_mass = 1.0 / ((90.0 - elevation).to_radians().cos() + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+        // Ångström turbidity from Preetham's fit; clear air still scatters a little
+        let beta = (0.04608 * self.turbidity - 0.04586).max(0.0);
+        let [r, g, b] = WAVELENGTHS.map(|lambda| {
+            let rayleigh = 0.008569 * lambda.powi(-4) * (1.0 + 0.0113 * lambda.powi(-2) + 0.00013 * lambda.powi(-4));
+            let aerosol = beta * lambda.powf(-1.3);
+            (-air_mass * (rayleigh + aerosol)).exp()
+        });
+        Vec3::new(r, g, b)
+    }
+}
+
+// Perez sky distribution for a view at zenith angle `theta`, `gamma` away from the sun
+fn perez_f(c: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    let cos_gamma = gamma.cos();
+    (1.0 + c[0] * (c[1] / theta.cos().max(0.01)).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+// CIE xyY chromaticity and luminance to linear sRGB
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3 {
+    let y = y.max(1e-4);
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Vec3::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+    .max(Vec3::zero())
+}
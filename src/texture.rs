@@ -1,4 +1,5 @@
 use crate::math::{Vec3, noise, fbm};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub enum TextureType {
@@ -9,6 +10,8 @@ pub enum TextureType {
     NetherPortal,
     Noise(NoiseConfig),
     MinecraftGrass,
+    MinecraftGrassSide,
+    MinecraftDirt,
     MinecraftStone,
     MinecraftWood,
     MinecraftDiamond,
@@ -17,6 +20,8 @@ pub enum TextureType {
     MinecraftIron,
     MinecraftGold,
     Procedural(fn(f32, f32, f32) -> Vec3),
+    Image(ImageTexture),
+    AnimatedImage(AnimatedImage),
 }
 
 #[derive(Clone, Copy)]
@@ -32,6 +37,126 @@ pub struct NoiseConfig {
     pub color2: Vec3,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureFilter { Nearest, Bilinear }
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureWrap { Repeat, Clamp }
+
+// Decoded image in linear colour space; texels are shared between clones
+#[derive(Clone)]
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    pub texels: Arc<Vec<Vec3>>,
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+}
+
+impl ImageTexture {
+    pub fn from_image(image: &image::DynamicImage) -> Self {
+        let rgb = image.to_rgb8();
+        let (width, height) = (rgb.width() as usize, rgb.height() as usize);
+        Self::from_rgb8(width, height, rgb.as_raw())
+    }
+    
+    // Tightly packed sRGB bytes, row 0 at the top
+    pub fn from_rgb8(width: usize, height: usize, data: &[u8]) -> Self {
+        assert_eq!(data.len(), width * height * 3, "image data does not match {}x{}", width, height);
+        let lut: Vec<f32> = (0..256).map(|i| srgb_to_linear(i as f32 / 255.0)).collect();
+        let texels = data
+            .chunks_exact(3)
+            .map(|p| Vec3::new(lut[p[0] as usize], lut[p[1] as usize], lut[p[2] as usize]))
+            .collect();
+        Self {
+            width,
+            height,
+            texels: Arc::new(texels),
+            filter: TextureFilter::Nearest,
+            wrap: TextureWrap::Repeat,
+        }
+    }
+    
+    // Multiplies every texel by a linear colour, e.g. biome tints for grayscale textures
+    pub fn tinted(&self, tint: Vec3) -> Self {
+        let texels = self.texels.iter().map(|&t| t * tint).collect();
+        Self { texels: Arc::new(texels), ..self.clone() }
+    }
+    
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let (x, y) = match self.wrap {
+            TextureWrap::Repeat => (x.rem_euclid(w), y.rem_euclid(h)),
+            TextureWrap::Clamp => (x.clamp(0, w - 1), y.clamp(0, h - 1)),
+        };
+        self.texels[(y * w + x) as usize]
+    }
+    
+    // v = 0 is the bottom of the image, matching the primitives' UV layout
+    pub fn sample(&self, u: f32, v: f32, filter: TextureFilter) -> Vec3 {
+        if self.width == 0 || self.height == 0 {
+            return Vec3::zero();
+        }
+        let x = u * self.width as f32;
+        let y = (1.0 - v) * self.height as f32;
+        match filter {
+            TextureFilter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            TextureFilter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.texel(x0, y0).lerp(self.texel(x0 + 1, y0), fx);
+                let bottom = self.texel(x0, y0 + 1).lerp(self.texel(x0 + 1, y0 + 1), fx);
+                top.lerp(bottom, fy)
+            }
+        }
+    }
+}
+
+// Flipbook of equally sized frames, as described by a Minecraft .mcmeta file
+#[derive(Clone)]
+pub struct AnimatedImage {
+    pub frames: Vec<ImageTexture>,
+    pub sequence: Vec<(usize, u32)>, // (frame index, duration in game ticks)
+    pub interpolate: bool,
+}
+
+impl AnimatedImage {
+    pub const TICKS_PER_SECOND: f32 = 20.0;
+    
+    pub fn sample(&self, u: f32, v: f32, time: f32, filter: TextureFilter) -> Vec3 {
+        let total: u32 = self.sequence.iter().map(|&(_, ticks)| ticks).sum();
+        if total == 0 {
+            return self.frames.first().map_or(Vec3::zero(), |frame| frame.sample(u, v, filter));
+        }
+        
+        let mut tick = (time * Self::TICKS_PER_SECOND).rem_euclid(total as f32);
+        for (i, &(frame, ticks)) in self.sequence.iter().enumerate() {
+            if tick < ticks as f32 {
+                let color = self.frames[frame].sample(u, v, filter);
+                if !self.interpolate {
+                    return color;
+                }
+                let next = self.sequence[(i + 1) % self.sequence.len()].0;
+                return color.lerp(self.frames[next].sample(u, v, filter), tick / ticks as f32);
+            }
+            tick -= ticks as f32;
+        }
+        // Only reachable through float rounding at the very end of the loop
+        let (last, _) = self.sequence[self.sequence.len() - 1];
+        self.frames[last].sample(u, v, filter)
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
 #[derive(Clone)]
 pub struct Texture {
     pub texture_type: TextureType,
@@ -121,6 +246,24 @@ impl Texture {
         }
     }
     
+    pub fn minecraft_grass_side() -> Self {
+        Texture {
+            texture_type: TextureType::MinecraftGrassSide,
+            scale: 1.0,
+            offset: (0.0, 0.0),
+            rotation: 0.0,
+        }
+    }
+    
+    pub fn minecraft_dirt() -> Self {
+        Texture {
+            texture_type: TextureType::MinecraftDirt,
+            scale: 1.0,
+            offset: (0.0, 0.0),
+            rotation: 0.0,
+        }
+    }
+    
     pub fn minecraft_stone() -> Self {
         Texture {
             texture_type: TextureType::MinecraftStone,
@@ -184,9 +327,47 @@ impl Texture {
         }
     }
     
-    pub fn from_file(_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        // For now, return a default texture since we're focusing on procedural textures
-        Ok(Self::solid_color(Vec3::new(0.8, 0.8, 0.8)))
+    // Decodes PNG/JPEG/TGA (anything the `image` crate reads) into linear texels
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let image = image::open(path)?;
+        Ok(Self::from_image(ImageTexture::from_image(&image)))
+    }
+    
+    pub fn from_image(image: ImageTexture) -> Self {
+        Texture {
+            texture_type: TextureType::Image(image),
+            scale: 1.0,
+            offset: (0.0, 0.0),
+            rotation: 0.0,
+        }
+    }
+    
+    // Filtering and addressing only affect image textures
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        match &mut self.texture_type {
+            TextureType::Image(image) => image.filter = filter,
+            TextureType::AnimatedImage(animation) => animation.frames.iter_mut().for_each(|frame| frame.filter = filter),
+            _ => {}
+        }
+        self
+    }
+    
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> Self {
+        match &mut self.texture_type {
+            TextureType::Image(image) => image.wrap = wrap,
+            TextureType::AnimatedImage(animation) => animation.frames.iter_mut().for_each(|frame| frame.wrap = wrap),
+            _ => {}
+        }
+        self
+    }
+    
+    pub fn animated_image(animation: AnimatedImage) -> Self {
+        Texture {
+            texture_type: TextureType::AnimatedImage(animation),
+            scale: 1.0,
+            offset: (0.0, 0.0),
+            rotation: 0.0,
+        }
     }
     
     pub fn with_scale(mut self, scale: f32) -> Self {
@@ -347,6 +528,26 @@ impl Texture {
                 }
             },
             
+            TextureType::MinecraftGrassSide | TextureType::MinecraftDirt => {
+                let noise_val = match quality {
+                    TextureQuality::High => noise(Vec3::new(u_transformed * 16.0, v_transformed * 16.0, 0.0)),
+                    TextureQuality::Medium => noise(Vec3::new(u_transformed * 8.0, v_transformed * 8.0, 0.0)),
+                    TextureQuality::Low => 0.4,
+                };
+                let dirt_dark = Vec3::new(0.3, 0.18, 0.08);
+                let dirt_light = Vec3::new(0.5, 0.33, 0.18);
+                let dirt = dirt_dark.lerp(dirt_light, noise_val);
+                
+                // Grass overhang along the top edge of side faces (v = 1 is the top)
+                if let TextureType::MinecraftGrassSide = self.texture_type {
+                    let fringe = 0.8 + (u_transformed * 37.0).sin().abs() * 0.08;
+                    if v_transformed > fringe {
+                        return Vec3::new(0.3, 0.6, 0.2).lerp(Vec3::new(0.5, 0.8, 0.3), noise_val);
+                    }
+                }
+                dirt
+            },
+            
             TextureType::MinecraftStone => {
                 let combined = match quality {
                     TextureQuality::High => {
@@ -471,6 +672,20 @@ impl Texture {
             },
             
             TextureType::Procedural(func) => func(u_transformed, v_transformed, time),
+            
+            TextureType::Image(image) => {
+                // Distant and deep-bounce lookups drop to a single texel fetch
+                let filter = if let TextureQuality::Low = quality { TextureFilter::Nearest } else { image.filter };
+                image.sample(u_transformed, v_transformed, filter)
+            },
+            
+            TextureType::AnimatedImage(animation) => {
+                let filter = match (quality, animation.frames.first()) {
+                    (TextureQuality::Low, _) | (_, None) => TextureFilter::Nearest,
+                    (_, Some(frame)) => frame.filter,
+                };
+                animation.sample(u_transformed, v_transformed, time, filter)
+            },
         }
     }
 }
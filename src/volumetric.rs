@@ -0,0 +1,59 @@
+use crate::math::{Ray, Vec3};
+
+// Participating medium filling the air: extinction is `density` up to `base_height` and
+// falls off exponentially above it (a falloff of zero gives homogeneous fog). Rendered by
+// ray marching with single scattering from the scene lights, so shadowed parts of the
+// medium stay dark and light shafts appear behind occluders.
+#[derive(Clone)]
+pub struct VolumetricFog {
+    pub color: Vec3, // Scattering albedo
+    pub density: f32,
+    pub base_height: f32,
+    pub height_falloff: f32,
+    pub anisotropy: f32, // Henyey–Greenstein g; above zero scatters forward, brightening the haze around lights
+    pub steps: u32,
+    pub max_distance: f32,
+}
+
+impl VolumetricFog {
+    pub fn homogeneous(color: Vec3, density: f32) -> Self {
+        Self {
+            color,
+            density,
+            base_height: 0.0,
+            height_falloff: 0.0,
+            anisotropy: 0.3,
+            steps: 16,
+            max_distance: 60.0,
+        }
+    }
+
+    pub fn height(color: Vec3, density: f32, base_height: f32, height_falloff: f32) -> Self {
+        Self { base_height, height_falloff, ..Self::homogeneous(color, density) }
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy.clamp(-0.95, 0.95);
+        self
+    }
+
+    // Extinction coefficient at `point`
+    pub fn density_at(&self, point: Vec3) -> f32 {
+        let above = (point.y - self.base_height).max(0.0);
+        self.density * (-self.height_falloff * above).exp()
+    }
+
+    // Henyey–Greenstein phase function; `cos_theta` is between the view ray and the
+    // direction towards the light
+    pub fn phase(&self, cos_theta: f32) -> f32 {
+        let g = self.anisotropy;
+        let denom = (1.0 + g * g - 2.0 * g * cos_theta).max(1e-4);
+        (1.0 - g * g) / (4.0 * std::f32::consts::PI * denom * denom.sqrt())
+    }
+
+    // Fraction of light surviving `distance` along `ray`, from the density at the midpoint
+    pub fn transmittance(&self, ray: &Ray, distance: f32) -> f32 {
+        let distance = distance.min(self.max_distance);
+        (-self.density_at(ray.point_at(distance * 0.5)) * distance).exp()
+    }
+}
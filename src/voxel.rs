@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::blocks::{BlockFace, BlockId, BlockMaterials, BlockRegistry, AIR};
+use crate::materials::{MaterialId, MaterialTable};
+use crate::math::*;
+use crate::primitives::Primitive;
+use crate::raytracer::{HitInfo, Light, LightType};
+
+pub const CHUNK_SIZE: i32 = 16;
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+pub struct Chunk {
+    blocks: Vec<BlockId>,
+    solid_count: usize,
+}
+
+impl Chunk {
+    fn new() -> Self {
+        Self {
+            blocks: vec![AIR; CHUNK_VOLUME],
+            solid_count: 0,
+        }
+    }
+
+    fn index(local: [i32; 3]) -> usize {
+        (local[0] + local[1] * CHUNK_SIZE + local[2] * CHUNK_SIZE * CHUNK_SIZE) as usize
+    }
+
+    fn get(&self, local: [i32; 3]) -> BlockId {
+        self.blocks[Self::index(local)]
+    }
+
+    fn set(&mut self, local: [i32; 3], id: BlockId) {
+        let slot = &mut self.blocks[Self::index(local)];
+        if *slot == AIR && id != AIR { self.solid_count += 1; }
+        if *slot != AIR && id == AIR { self.solid_count -= 1; }
+        *slot = id;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.solid_count == 0
+    }
+}
+
+// Sparse grid of unit blocks stored in 16^3 chunks.
+// Block (x, y, z) occupies [origin + (x, y, z), origin + (x, y, z) + 1].
+pub struct VoxelWorld {
+    pub origin: Vec3,
+    registry: Arc<BlockRegistry>,
+    materials: BlockMaterials,
+    chunks: HashMap<[i32; 3], Chunk>,
+    min_block: [i32; 3],
+    max_block: [i32; 3],
+}
+
+impl VoxelWorld {
+    // Block face materials are added to `materials`; hits refer to them by ID
+    pub fn new(origin: Vec3, registry: Arc<BlockRegistry>, materials: &mut MaterialTable) -> Self {
+        Self {
+            origin,
+            materials: registry.register_materials(materials),
+            registry,
+            chunks: HashMap::new(),
+            min_block: [i32::MAX; 3],
+            max_block: [i32::MIN; 3],
+        }
+    }
+
+    // World whose blocks are centred on integer coordinates, matching unit `Cube`s
+    pub fn centered(registry: Arc<BlockRegistry>, materials: &mut MaterialTable) -> Self {
+        Self::new(Vec3::new(-0.5, -0.5, -0.5), registry, materials)
+    }
+
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+
+    pub fn block_materials(&self) -> &BlockMaterials {
+        &self.materials
+    }
+
+    fn split(x: i32, y: i32, z: i32) -> ([i32; 3], [i32; 3]) {
+        let chunk = [x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE), z.div_euclid(CHUNK_SIZE)];
+        let local = [x.rem_euclid(CHUNK_SIZE), y.rem_euclid(CHUNK_SIZE), z.rem_euclid(CHUNK_SIZE)];
+        (chunk, local)
+    }
+
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> BlockId {
+        let (chunk, local) = Self::split(x, y, z);
+        self.chunks.get(&chunk).map(|c| c.get(local)).unwrap_or(AIR)
+    }
+
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, id: BlockId) {
+        assert!((id as usize) < self.registry.len(), "unknown block id {}", id);
+        let (chunk, local) = Self::split(x, y, z);
+        if id == AIR && !self.chunks.contains_key(&chunk) {
+            return;
+        }
+        self.chunks.entry(chunk).or_insert_with(Chunk::new).set(local, id);
+        if id != AIR {
+            self.min_block = [self.min_block[0].min(x), self.min_block[1].min(y), self.min_block[2].min(z)];
+            self.max_block = [self.max_block[0].max(x), self.max_block[1].max(y), self.max_block[2].max(z)];
+        }
+    }
+
+    // Fill the inclusive block range [min, max]
+    pub fn fill(&mut self, min: (i32, i32, i32), max: (i32, i32, i32), id: BlockId) {
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    self.set_block(x, y, z, id);
+                }
+            }
+        }
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.chunks.values().map(|c| c.solid_count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_count() == 0
+    }
+
+    fn block_in_chunk(chunk: &Chunk, cell: [i32; 3]) -> BlockId {
+        chunk.get([cell[0].rem_euclid(CHUNK_SIZE), cell[1].rem_euclid(CHUNK_SIZE), cell[2].rem_euclid(CHUNK_SIZE)])
+    }
+
+    // Two-level Amanatides-Woo traversal: step through chunks, and only walk
+    // individual blocks inside chunks that contain something. A hit is reported
+    // wherever the block type changes, so runs of identical blocks (water, glass)
+    // behave like a single volume and rays leaving a block report its exit face.
+    // Chunks starting beyond `max_t` are not visited.
+    fn traverse(&self, ray: &Ray, max_t: f32) -> Option<VoxelHit> {
+        // Bounds start inverted and only grow, so this is the cheap emptiness test
+        if self.min_block[0] > self.max_block[0] { return None; }
+        
+        let origin = ray.origin - self.origin;
+        let dir = ray.direction;
+        let box_min = Vec3::new(self.min_block[0] as f32, self.min_block[1] as f32, self.min_block[2] as f32);
+        let box_max = Vec3::new(self.max_block[0] as f32 + 1.0, self.max_block[1] as f32 + 1.0, self.max_block[2] as f32 + 1.0);
+        let (t_enter, t_exit, enter_axis) = clip_ray_to_box(origin, dir, box_min, box_max)?;
+        if t_exit < 0.0 { return None; }
+
+        // The block the ray starts in (if any) is the medium we are leaving
+        let (t_start, mut prev, mut entry_axis) = if t_enter > 0.0 {
+            (t_enter, AIR, Some(enter_axis))
+        } else {
+            let p = origin.floor();
+            (0.0, self.get_block(p.x as i32, p.y as i32, p.z as i32), None)
+        };
+
+        let mut chunks = Dda::new(origin, dir, CHUNK_SIZE as f32, t_start);
+        let mut t_chunk = t_start;
+        loop {
+            if t_chunk >= max_t { return None; }
+            let t_chunk_out = chunks.next_crossing().min(t_exit);
+            if let Some(chunk) = self.chunks.get(&chunks.cell).filter(|c| !c.is_empty()) {
+                let lo = [chunks.cell[0] * CHUNK_SIZE, chunks.cell[1] * CHUNK_SIZE, chunks.cell[2] * CHUNK_SIZE];
+                let mut blocks = Dda::new(origin, dir, 1.0, t_chunk);
+                for (cell, lo) in blocks.cell.iter_mut().zip(lo) {
+                    *cell = (*cell).clamp(lo, lo + CHUNK_SIZE - 1);
+                }
+                let mut t_block = t_chunk;
+                loop {
+                    let block = Self::block_in_chunk(chunk, blocks.cell);
+                    if block != prev {
+                        if let Some(axis) = entry_axis {
+                            if t_block > 0.001 {
+                                return Some(VoxelHit { t: t_block, axis, step: blocks.step[axis], block, prev });
+                            }
+                        }
+                        prev = block;
+                    }
+                    t_block = blocks.next_crossing();
+                    if t_block >= t_chunk_out { break; }
+                    let axis = blocks.advance();
+                    if (0..3).any(|a| blocks.cell[a] < lo[a] || blocks.cell[a] >= lo[a] + CHUNK_SIZE) { break; }
+                    entry_axis = Some(axis);
+                }
+            } else if prev != AIR {
+                // Leaving a block straight into an empty chunk
+                if let Some(axis) = entry_axis {
+                    if t_chunk > 0.001 {
+                        return Some(VoxelHit { t: t_chunk, axis, step: chunks.step[axis], block: AIR, prev });
+                    }
+                }
+                prev = AIR;
+            }
+            if t_chunk_out >= t_exit { break; }
+            t_chunk = t_chunk_out;
+            entry_axis = Some(chunks.advance());
+        }
+
+        // Ray started inside a block and left the world bounds without another change
+        if prev != AIR && t_exit > 0.001 {
+            let axis = exit_axis(origin, dir, box_min, box_max);
+            let step = if dir_component(dir, axis) > 0.0 { 1 } else { -1 };
+            return Some(VoxelHit { t: t_exit, axis, step, block: AIR, prev });
+        }
+        None
+    }
+}
+
+struct VoxelHit {
+    t: f32,
+    axis: usize,
+    step: i32,
+    block: BlockId,
+    prev: BlockId,
+}
+
+impl Primitive for VoxelWorld {
+    fn intersect(&self, ray: &Ray, _time: f32) -> Option<HitInfo> {
+        let hit = self.traverse(ray, f32::INFINITY)?;
+
+        // Entering a block shows its front face; leaving one shows its back face
+        let (block, outward) = if hit.block != AIR { (hit.block, -hit.step) } else { (hit.prev, hit.step) };
+        let mut normal = Vec3::zero();
+        match hit.axis {
+            0 => normal.x = outward as f32,
+            1 => normal.y = outward as f32,
+            _ => normal.z = outward as f32,
+        }
+
+        let point = ray.point_at(hit.t);
+        let local = point - self.origin;
+        let fx = (local.x - local.x.floor()).clamp(0.0, 1.0);
+        let fy = (local.y - local.y.floor()).clamp(0.0, 1.0);
+        let fz = (local.z - local.z.floor()).clamp(0.0, 1.0);
+
+        // Same per-face UV layout as `Cube`
+        let uv = match hit.axis {
+            0 => (fz, fy),
+            1 => (fx, fz),
+            _ => (fx, fy),
+        };
+
+        Some(HitInfo {
+            t: hit.t,
+            point,
+            normal,
+            material: self.materials.get(block, BlockFace::from_normal(normal)),
+            uv,
+        })
+    }
+
+    fn get_bounds(&self) -> (Vec3, Vec3) {
+        if self.is_empty() {
+            return (self.origin, self.origin);
+        }
+        let min = Vec3::new(self.min_block[0] as f32, self.min_block[1] as f32, self.min_block[2] as f32);
+        let max = Vec3::new(self.max_block[0] as f32 + 1.0, self.max_block[1] as f32 + 1.0, self.max_block[2] as f32 + 1.0);
+        (self.origin + min, self.origin + max)
+    }
+
+    fn occluded(&self, ray: &Ray, max_t: f32, _time: f32) -> bool {
+        self.traverse(ray, max_t).is_some_and(|hit| hit.t < max_t)
+    }
+    
+    // One unit box light per block with a light level (glowstone, portal, fire), coloured
+    // by its emissive face and scaled by level / 15. Emissive faces on a block without a
+    // light level still glow when seen, but don't light their surroundings.
+    fn emitters(&self, materials: &MaterialTable, lights: &mut Vec<Light>) {
+        let emissive: Vec<Option<(MaterialId, f32)>> = (0..self.registry.len() as BlockId)
+            .map(|id| {
+                let light_level = self.registry.get(id).light_level;
+                if id == AIR || light_level == 0 {
+                    return None;
+                }
+                [BlockFace::Side, BlockFace::Top, BlockFace::Bottom]
+                    .into_iter()
+                    .map(|face| self.materials.get(id, face))
+                    .find(|&material| materials.get(material).is_emissive())
+                    .map(|material| (material, light_level as f32 / 15.0))
+            })
+            .collect();
+        if emissive.iter().all(Option::is_none) {
+            return;
+        }
+        
+        let half_extents = Vec3::new(0.5, 0.5, 0.5);
+        for (key, chunk) in &self.chunks {
+            for (index, &block) in chunk.blocks.iter().enumerate() {
+                let (material, scale) = match emissive[block as usize] {
+                    Some(emitter) => emitter,
+                    None => continue,
+                };
+                let index = index as i32;
+                let x = key[0] * CHUNK_SIZE + index % CHUNK_SIZE;
+                let y = key[1] * CHUNK_SIZE + (index / CHUNK_SIZE) % CHUNK_SIZE;
+                let z = key[2] * CHUNK_SIZE + index / (CHUNK_SIZE * CHUNK_SIZE);
+                let center = self.origin + Vec3::new(x as f32, y as f32, z as f32) + half_extents;
+                let mut light = Light::emitter(center, LightType::Box { half_extents }, materials.get(material));
+                light.intensity *= scale;
+                lights.push(light);
+            }
+        }
+    }
+}
+
+// Incremental grid walker over cells of `cell_size` (grid-local coordinates)
+struct Dda {
+    cell: [i32; 3],
+    step: [i32; 3],
+    t_max: [f32; 3],
+    t_delta: [f32; 3],
+}
+
+impl Dda {
+    fn new(origin: Vec3, dir: Vec3, cell_size: f32, t: f32) -> Self {
+        let cell = dda_cell_at(origin, dir, t, cell_size);
+        let o = [origin.x, origin.y, origin.z];
+        let d = [dir.x, dir.y, dir.z];
+        let mut step = [0; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+        for a in 0..3 {
+            if d[a] > 0.0 {
+                step[a] = 1;
+                t_max[a] = ((cell[a] + 1) as f32 * cell_size - o[a]) / d[a];
+                t_delta[a] = cell_size / d[a];
+            } else if d[a] < 0.0 {
+                step[a] = -1;
+                t_max[a] = (cell[a] as f32 * cell_size - o[a]) / d[a];
+                t_delta[a] = -cell_size / d[a];
+            }
+        }
+        Self { cell, step, t_max, t_delta }
+    }
+
+    fn axis(&self) -> usize {
+        if self.t_max[0] < self.t_max[1] {
+            if self.t_max[0] < self.t_max[2] { 0 } else { 2 }
+        } else if self.t_max[1] < self.t_max[2] { 1 } else { 2 }
+    }
+
+    // Distance along the ray at which the current cell is left
+    fn next_crossing(&self) -> f32 {
+        self.t_max[self.axis()]
+    }
+
+    // Step into the neighbouring cell, returning the axis that was crossed
+    fn advance(&mut self) -> usize {
+        let axis = self.axis();
+        self.cell[axis] += self.step[axis];
+        self.t_max[axis] += self.t_delta[axis];
+        axis
+    }
+}
+
+fn dda_cell_at(origin: Vec3, dir: Vec3, t: f32, cell_size: f32) -> [i32; 3] {
+    // Nudge along the ray so points on a cell face land in the cell being entered
+    let p = origin + dir * (t + 1e-4);
+    [
+        (p.x / cell_size).floor() as i32,
+        (p.y / cell_size).floor() as i32,
+        (p.z / cell_size).floor() as i32,
+    ]
+}
+
+fn dir_component(v: Vec3, axis: usize) -> f32 {
+    match axis { 0 => v.x, 1 => v.y, _ => v.z }
+}
+
+// Slab test returning entry/exit distances and the axis of the entry face
+fn clip_ray_to_box(origin: Vec3, dir: Vec3, bmin: Vec3, bmax: Vec3) -> Option<(f32, f32, usize)> {
+    let mut t_enter = f32::NEG_INFINITY;
+    let mut t_exit = f32::INFINITY;
+    let mut axis = 0;
+    for a in 0..3 {
+        let o = dir_component(origin, a);
+        let d = dir_component(dir, a);
+        let (lo, hi) = (dir_component(bmin, a), dir_component(bmax, a));
+        if d.abs() < 1e-8 {
+            if o < lo || o > hi { return None; }
+            continue;
+        }
+        let mut t0 = (lo - o) / d;
+        let mut t1 = (hi - o) / d;
+        if t0 > t1 { std::mem::swap(&mut t0, &mut t1); }
+        if t0 > t_enter { t_enter = t0; axis = a; }
+        t_exit = t_exit.min(t1);
+    }
+    if t_enter > t_exit { None } else { Some((t_enter, t_exit, axis)) }
+}
+
+fn exit_axis(origin: Vec3, dir: Vec3, bmin: Vec3, bmax: Vec3) -> usize {
+    let mut best = f32::INFINITY;
+    let mut axis = 0;
+    for a in 0..3 {
+        let d = dir_component(dir, a);
+        if d.abs() < 1e-8 { continue; }
+        let bound = if d > 0.0 { dir_component(bmax, a) } else { dir_component(bmin, a) };
+        let t = (bound - dir_component(origin, a)) / d;
+        if t < best { best = t; axis = a; }
+    }
+    axis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_with(blocks: &[(i32, i32, i32)]) -> (VoxelWorld, BlockId) {
+        let registry = Arc::new(BlockRegistry::minecraft());
+        let stone = registry.expect_id("minecraft:stone");
+        let mut world = VoxelWorld::new(Vec3::zero(), registry, &mut MaterialTable::new());
+        for &(x, y, z) in blocks {
+            world.set_block(x, y, z, stone);
+        }
+        (world, stone)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn hits_each_entry_face_with_its_normal() {
+        let (world, stone) = world_with(&[(0, 0, 0)]);
+        let center = Vec3::new(0.5, 0.5, 0.5);
+        for normal in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ] {
+            let hit = world.intersect(&Ray::new(center + normal * 5.0, -normal), 0.0).unwrap();
+            assert_close(hit.t, 4.5);
+            assert_close((hit.normal - normal).length(), 0.0);
+            assert_eq!(hit.material, world.block_materials().get(stone, BlockFace::from_normal(normal)));
+        }
+    }
+
+    #[test]
+    fn crosses_chunk_boundaries() {
+        // Straight along x through two empty chunks
+        let (world, _) = world_with(&[(40, 3, -20), (33, 17, 18)]);
+        let hit = world.intersect(&Ray::new(Vec3::new(-5.0, 3.5, -19.5), Vec3::new(1.0, 0.0, 0.0)), 0.0).unwrap();
+        assert_close(hit.t, 45.0);
+        assert_close(hit.normal.x, -1.0);
+
+        // Diagonally through chunks on every axis, entering the block's -x face
+        let direction = Vec3::new(33.0, 17.0, 18.0);
+        let hit = world.intersect(&Ray::new(Vec3::new(0.5, 0.5, 0.5), direction), 0.0).unwrap();
+        assert_close(hit.t, direction.length() * 32.5 / 33.0);
+        assert_close(hit.normal.x, -1.0);
+    }
+
+    #[test]
+    fn ray_starting_inside_reports_the_exit_face() {
+        // Identical neighbours form one volume, so the exit is the far side of the run
+        let (world, _) = world_with(&[(0, 0, 0), (1, 0, 0)]);
+        let hit = world.intersect(&Ray::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0)), 0.0).unwrap();
+        assert_close(hit.t, 1.5);
+        assert_close(hit.normal.x, 1.0);
+    }
+
+    #[test]
+    fn rays_outside_the_grid_bounds_miss() {
+        let (world, _) = world_with(&[(0, 0, 0), (20, 0, 0)]);
+        let above = Ray::new(Vec3::new(-5.0, 2.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert!(world.intersect(&above, 0.0).is_none());
+        assert!(!world.occluded(&above, f32::INFINITY, 0.0));
+        let away = Ray::new(Vec3::new(-5.0, 0.5, 0.5), Vec3::new(-1.0, 0.0, 0.0));
+        assert!(world.intersect(&away, 0.0).is_none());
+    }
+
+    #[test]
+    fn occlusion_stops_at_max_t() {
+        let (world, _) = world_with(&[(10, 0, 0), (40, 0, 0)]);
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert!(!world.occluded(&ray, 9.0, 0.0));
+        assert!(world.occluded(&ray, 10.0, 0.0));
+
+        // Past the first block, the next one is two chunks away
+        let behind = Ray::new(Vec3::new(11.5, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert!(!world.occluded(&behind, 28.0, 0.0));
+        assert!(world.occluded(&behind, 29.0, 0.0));
+    }
+
+    #[test]
+    fn light_level_scales_extracted_emitters() {
+        let registry = Arc::new(BlockRegistry::minecraft());
+        let mut materials = MaterialTable::new();
+        let mut world = VoxelWorld::new(Vec3::zero(), registry.clone(), &mut materials);
+        world.set_block(0, 0, 0, registry.expect_id("minecraft:stone"));
+        world.set_block(2, 0, 0, registry.expect_id("minecraft:nether_portal"));
+
+        let mut lights = Vec::new();
+        world.emitters(&materials, &mut lights);
+        assert_eq!(lights.len(), 1);
+        let portal = world.block_materials().get(registry.expect_id("minecraft:nether_portal"), BlockFace::Side);
+        let full = Light::emitter(Vec3::zero(), LightType::Box { half_extents: Vec3::new(0.5, 0.5, 0.5) }, materials.get(portal));
+        assert_close(lights[0].intensity, full.intensity * 11.0 / 15.0);
+        assert_close(lights[0].position.x, 2.5);
+    }
+}
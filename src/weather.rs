@@ -0,0 +1,181 @@
+use crate::math::{hash, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeatherKind { Clear, Rain, Snow, Thunder }
+
+// What the renderer needs from the weather at one moment. Clear skies leave everything at
+// zero, so a scene that never sets it renders as before.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WeatherConditions {
+    pub rain: f32,      // Falling rain, 0..1
+    pub snowfall: f32,  // Falling snow, 0..1
+    pub overcast: f32,  // Cloud cover greying the sky and dimming the sun
+    pub wetness: f32,   // Rain left on exposed surfaces; builds up and dries with time
+    pub snow_cover: f32, // Snow lying on top faces; builds up and melts with time
+    pub lightning: f32, // Current flash strength
+    pub lightning_direction: Vec3, // Direction the flash light travels
+}
+
+// Each spell of weather lasts this long; the first `TRANSITION` seconds blend from the last one
+const SPELL_DURATION: f32 = 60.0;
+const TRANSITION: f32 = 8.0;
+// Time constants (seconds) for surfaces getting wet or snowy, and for drying and melting
+const WETTING_TIME: f32 = 6.0;
+const DRYING_TIME: f32 = 30.0;
+const SNOWING_TIME: f32 = 20.0;
+const MELTING_TIME: f32 = 40.0;
+// Thunderstorms get a chance of one flash per slot
+const FLASH_SLOT: f32 = 3.0;
+const FLASH_DURATION: f32 = 0.6;
+
+// Weather state machine. `Auto` walks a Markov chain of spells (clear skies can turn to
+// rain or snow, rain to thunder, storms back to rain) seeded by the spell index, so the
+// weather is a pure function of the scene time and scrubbing or headless renders agree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Weather {
+    Auto,
+    Fixed(WeatherKind),
+}
+
+impl Weather {
+    pub fn conditions(&self, time: f32) -> WeatherConditions {
+        let (profile, wetness, snow_cover) = match *self {
+            // As if it had been like this for a long time
+            Weather::Fixed(kind) => {
+                let profile = WeatherProfile::of(kind);
+                (profile, profile.rain, profile.snowfall)
+            },
+            Weather::Auto => {
+                let (wetness, snow_cover) = ground_cover(time);
+                (blended_profile(time), wetness, snow_cover)
+            },
+        };
+        let (lightning, lightning_direction) = lightning_flash(time, profile.thunder).unwrap_or((0.0, Vec3::zero()));
+        WeatherConditions {
+            rain: profile.rain,
+            snowfall: profile.snowfall,
+            overcast: profile.overcast,
+            wetness,
+            snow_cover,
+            lightning,
+            lightning_direction,
+        }
+    }
+}
+
+// Target intensities of one kind of weather
+#[derive(Clone, Copy)]
+struct WeatherProfile {
+    rain: f32,
+    snowfall: f32,
+    overcast: f32,
+    thunder: f32,
+}
+
+impl WeatherProfile {
+    fn of(kind: WeatherKind) -> Self {
+        match kind {
+            WeatherKind::Clear => Self { rain: 0.0, snowfall: 0.0, overcast: 0.0, thunder: 0.0 },
+            WeatherKind::Rain => Self { rain: 1.0, snowfall: 0.0, overcast: 0.7, thunder: 0.0 },
+            WeatherKind::Snow => Self { rain: 0.0, snowfall: 1.0, overcast: 0.6, thunder: 0.0 },
+            WeatherKind::Thunder => Self { rain: 1.0, snowfall: 0.0, overcast: 0.95, thunder: 1.0 },
+        }
+    }
+
+    fn lerp(self, other: Self, t: f32) -> Self {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        Self {
+            rain: mix(self.rain, other.rain),
+            snowfall: mix(self.snowfall, other.snowfall),
+            overcast: mix(self.overcast, other.overcast),
+            thunder: mix(self.thunder, other.thunder),
+        }
+    }
+}
+
+fn random(seed: f32, salt: f32) -> f32 {
+    hash(Vec3::new(seed, salt, 0.0)).abs()
+}
+
+fn spell_index(time: f32) -> i32 {
+    (time / SPELL_DURATION).floor() as i32
+}
+
+// Clear until the first spell ends, then one Markov step per spell
+fn spell_kind(index: i32) -> WeatherKind {
+    let mut kind = WeatherKind::Clear;
+    for spell in 1..=index {
+        kind = next_kind(kind, random(spell as f32, 1.7));
+    }
+    kind
+}
+
+fn next_kind(kind: WeatherKind, roll: f32) -> WeatherKind {
+    match kind {
+        WeatherKind::Clear if roll < 0.35 => WeatherKind::Rain,
+        WeatherKind::Clear if roll < 0.55 => WeatherKind::Snow,
+        WeatherKind::Clear => WeatherKind::Clear,
+        WeatherKind::Rain if roll < 0.3 => WeatherKind::Thunder,
+        WeatherKind::Rain if roll < 0.5 => WeatherKind::Rain,
+        WeatherKind::Rain => WeatherKind::Clear,
+        WeatherKind::Thunder if roll < 0.7 => WeatherKind::Rain,
+        WeatherKind::Thunder => WeatherKind::Clear,
+        WeatherKind::Snow if roll < 0.4 => WeatherKind::Snow,
+        WeatherKind::Snow => WeatherKind::Clear,
+    }
+}
+
+fn blended_profile(time: f32) -> WeatherProfile {
+    let index = spell_index(time);
+    let current = WeatherProfile::of(spell_kind(index));
+    if index <= 0 {
+        return current;
+    }
+    let previous = WeatherProfile::of(spell_kind(index - 1));
+    let elapsed = time - index as f32 * SPELL_DURATION;
+    previous.lerp(current, Vec3::smoothstep(0.0, TRANSITION, elapsed))
+}
+
+// Wetness and snow cover at `time`, integrated spell by spell towards each spell's target
+fn ground_cover(time: f32) -> (f32, f32) {
+    let (mut wetness, mut snow_cover) = (0.0f32, 0.0f32);
+    let mut kind = WeatherKind::Clear;
+    for spell in 0..=spell_index(time).max(0) {
+        if spell > 0 {
+            kind = next_kind(kind, random(spell as f32, 1.7));
+        }
+        let start = spell as f32 * SPELL_DURATION;
+        let duration = (time - start).clamp(0.0, SPELL_DURATION);
+        let target = WeatherProfile::of(kind);
+        wetness = approach(wetness, target.rain, duration, WETTING_TIME, DRYING_TIME);
+        snow_cover = approach(snow_cover, target.snowfall, duration, SNOWING_TIME, MELTING_TIME);
+    }
+    (wetness, snow_cover)
+}
+
+// Exponential approach to `target` over `duration`, with separate rising and falling rates
+fn approach(value: f32, target: f32, duration: f32, rise_time: f32, fall_time: f32) -> f32 {
+    let time_constant = if target > value { rise_time } else { fall_time };
+    target + (value - target) * (-duration / time_constant).exp()
+}
+
+// Flash strength and light direction if a lightning strike is lighting the scene at `time`
+fn lightning_flash(time: f32, thunder: f32) -> Option<(f32, Vec3)> {
+    if thunder <= 0.0 {
+        return None;
+    }
+    let slot = (time / FLASH_SLOT).floor();
+    if random(slot, 3.1) > thunder * 0.5 {
+        return None;
+    }
+    let strike = slot * FLASH_SLOT + random(slot, 5.3) * (FLASH_SLOT - FLASH_DURATION);
+    let since = time - strike;
+    if !(0.0..FLASH_DURATION).contains(&since) {
+        return None;
+    }
+    // A bright stroke that flickers as it fades
+    let flash = (-since * 8.0).exp() * (0.7 + 0.3 * (since * 60.0).cos());
+    let azimuth = random(slot, 7.9) * std::f32::consts::TAU;
+    let direction = -Vec3::new(azimuth.cos() * 0.6, 1.0, azimuth.sin() * 0.6).normalize();
+    Some((flash * thunder, direction))
+}